@@ -10,11 +10,12 @@ pub struct BlameEntry<'a> {
     name: &'a str,
     duration: u128,
     level: usize,
+    note: Option<&'a str>,
 }
 
 impl<'a> BlameEntry<'a> {
     pub fn parse_single_entry(line: &'a str) -> Result<Self> {
-        let mut parts = line.split_whitespace();
+        let mut parts = line.splitn(4, ' ');
 
         let name = parts.next().context("expected `name`")?;
 
@@ -30,15 +31,18 @@ impl<'a> BlameEntry<'a> {
             .parse::<usize>()
             .context("failed to parse `level`")?;
 
+        let note = parts.next();
+
         Ok(Self {
             name,
             duration,
             level,
+            note,
         })
     }
 }
 
-fn parse_blame(lines: &str) -> Vec<BlameEntry> {
+fn parse_blame(lines: &str) -> Vec<BlameEntry<'_>> {
     lines
         .lines()
         .filter_map(|line| BlameEntry::parse_single_entry(line).ok())
@@ -56,6 +60,7 @@ pub fn blame(opts: Blame) -> Result<()> {
         .map(|l| BlameEntry {
             name: l.name,
             level: l.level,
+            note: l.note,
             duration: ((l.duration as f64) / 1000.0).round() as u128,
         })
         .collect::<Vec<_>>();
@@ -74,12 +79,22 @@ pub fn blame(opts: Blame) -> Result<()> {
     for timing in filtered_timings {
         let dur = timing.duration.to_string();
 
-        println!(
-            "{}{}ms {}",
-            " ".repeat(max_len - dur.len()),
-            dur,
-            &timing.name[5..]
-        )
+        if let Some(note) = timing.note {
+            println!(
+                "{}{}ms {} ({})",
+                " ".repeat(max_len - dur.len()),
+                dur,
+                &timing.name[5..],
+                note
+            )
+        } else {
+            println!(
+                "{}{}ms {}",
+                " ".repeat(max_len - dur.len()),
+                dur,
+                &timing.name[5..]
+            )
+        }
     }
 
     Ok(())
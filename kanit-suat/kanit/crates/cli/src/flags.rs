@@ -20,11 +20,30 @@ xflags::xflags! {
             /// Force a reboot via kexec, not performing a teardown.
             optional -f, --force
         }
+        /// Switch to a level at runtime.
+        cmd runlevel {
+            /// The index or name of the level, such as `default` or `shutdown`.
+            required level: String
+        }
         /// Print unit startup times.
         cmd blame {
             // Print units sorted by startup time.
             optional -s, --sorted
         }
+        /// Print the state of every unit.
+        cmd status {
+            /// Print the status as JSON.
+            optional --json
+        }
+        /// Print unit state changes, restarts, reloads and power events as they happen.
+        cmd monitor {
+            /// Only print events of a unit, along with those about the whole system.
+            optional -u, --unit unit: String
+            /// Print recent events, such as those of the boot, first.
+            optional -r, --replay
+            /// Print each event as a JSON object on its own line.
+            optional --json
+        }
         /// Service related utilities.
        cmd service {
             /// Enable a unit at the specified runlevel.
@@ -46,6 +65,60 @@ xflags::xflags! {
                 /// Shows the individual unit groups.
                 optional -p, --plan
             }
+            /// Start a unit and the units it needs.
+            cmd start {
+                /// The name of the unit.
+                required unit: String
+                /// The level to start the unit at.
+                optional -l, --level level: usize
+                /// Return without waiting for the unit to start.
+                optional --no-block
+            }
+            /// Stop a unit and the units that need it.
+            cmd stop {
+                /// The name of the unit.
+                required unit: String
+                /// The level to stop the unit at.
+                optional -l, --level level: usize
+                /// Return without waiting for the unit to stop.
+                optional --no-block
+            }
+            /// Stop and start a running unit.
+            cmd restart {
+                /// The name of the unit.
+                required unit: String
+                /// The level the unit was started at.
+                optional -l, --level level: usize
+                /// Return without waiting for the unit to restart.
+                optional --no-block
+            }
+            /// Print a unit with its drop-ins and the merged result.
+            cmd cat {
+                /// The name of the unit.
+                required unit: String
+            }
+            /// Print the state, dependencies and recent logs of a unit.
+            cmd show status {
+                /// The name of the unit.
+                required unit: String
+                /// Print the status as JSON.
+                optional --json
+            }
+            /// Reset a unit which failed from restarting too often.
+            cmd reset {
+                /// The name of the unit.
+                required unit: String
+            }
+            /// Prevent a unit from being started, even as a dependency.
+            cmd mask {
+                /// The name of the unit.
+                required unit: String
+            }
+            /// Allow a masked unit to be started again.
+            cmd unmask {
+                /// The name of the unit.
+                required unit: String
+            }
        }
     }
 }
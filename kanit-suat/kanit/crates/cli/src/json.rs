@@ -0,0 +1,141 @@
+use std::fmt::{self, Display, Formatter, Write};
+
+/// Just enough JSON for `--json` output, without pulling in a serializer.
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+macro_rules! int {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for Json {
+            fn from(value: $ty) -> Self {
+                Self::Int(value as i64)
+            }
+        })*
+    };
+}
+
+int!(i32, u32, u64, usize);
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Self::Str(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Self::Str(value)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(value: Vec<T>) -> Self {
+        Self::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+fn escape(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Str(s) => escape(f, s),
+            Self::Array(values) => {
+                f.write_char('[')?;
+
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                f.write_char(']')
+            }
+            Self::Object(fields) => {
+                f.write_char('{')?;
+
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+
+                    escape(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_strings() {
+        assert_eq!(Json::from("plain").to_string(), r#""plain""#);
+        assert_eq!(Json::from(r#"a "b" \c"#).to_string(), r#""a \"b\" \\c""#);
+        assert_eq!(Json::from("a\nb\r\tc").to_string(), r#""a\nb\r\tc""#);
+        assert_eq!(Json::from("\u{1}\u{1f}").to_string(), r#""\u0001\u001f""#);
+        assert_eq!(Json::from("ünït ✓").to_string(), "\"ünït ✓\"");
+    }
+
+    #[test]
+    fn nested_values() {
+        let json = Json::Object(vec![
+            ("name", "getty@tty1".into()),
+            ("pid", Some(42u32).into()),
+            ("error", None::<String>.into()),
+            ("active", true.into()),
+            ("needs", vec!["a\"b", "c"].into()),
+        ]);
+
+        assert_eq!(
+            json.to_string(),
+            r#"{"name":"getty@tty1","pid":42,"error":null,"active":true,"needs":["a\"b","c"]}"#
+        );
+        assert_eq!(Json::Array(vec![]).to_string(), "[]");
+        assert_eq!(Json::Object(vec![]).to_string(), "{}");
+    }
+}
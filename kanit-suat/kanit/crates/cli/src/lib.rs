@@ -7,8 +7,12 @@ use flags::{Kanit, KanitCmd};
 #[cfg(feature = "blame")]
 mod blame;
 mod flags;
+mod json;
+mod monitor;
+mod runlevel;
 #[cfg(feature = "service")]
 mod service;
+mod status;
 mod teardown;
 
 pub fn handle_cli() -> ExitCode {
@@ -18,6 +22,9 @@ pub fn handle_cli() -> ExitCode {
             KanitCmd::Reboot(opts) => teardown::teardown("reboot", opts.force),
             KanitCmd::Halt(opts) => teardown::teardown("halt", opts.force),
             KanitCmd::Kexec(opts) => teardown::teardown("kexec", opts.force),
+            KanitCmd::Runlevel(opts) => runlevel::runlevel(opts),
+            KanitCmd::Status(opts) => status::status(opts),
+            KanitCmd::Monitor(opts) => monitor::monitor(opts),
             #[cfg(feature = "blame")]
             KanitCmd::Blame(opts) => blame::blame(opts),
             #[cfg(not(feature = "blame"))]
@@ -30,6 +37,14 @@ pub fn handle_cli() -> ExitCode {
                 ServiceCmd::Enable(opts) => service::enable(opts),
                 ServiceCmd::Disable(opts) => service::disable(opts),
                 ServiceCmd::List(opts) => service::list(opts),
+                ServiceCmd::Start(opts) => service::start(opts),
+                ServiceCmd::Stop(opts) => service::stop(opts),
+                ServiceCmd::Restart(opts) => service::restart(opts),
+                ServiceCmd::Cat(opts) => service::cat(opts),
+                ServiceCmd::Show(opts) => service::show(opts),
+                ServiceCmd::Reset(opts) => service::reset(opts),
+                ServiceCmd::Mask(opts) => service::mask(opts),
+                ServiceCmd::Unmask(opts) => service::unmask(opts),
             },
             #[cfg(not(feature = "service"))]
             KanitCmd::Service(_) => {
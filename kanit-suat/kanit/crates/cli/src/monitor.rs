@@ -0,0 +1,104 @@
+use kanit_common::error::Result;
+use kanit_control::{subscribe, Event, EventKind};
+
+use crate::flags::Monitor;
+use crate::json::Json;
+
+/// The schema is kept stable, fields are only ever added.
+fn event_json(event: &Event) -> Json {
+    let mut fields = vec![("time", event.time.into())];
+
+    match &event.kind {
+        EventKind::State {
+            unit,
+            state,
+            pid,
+            error,
+            exit_status,
+        } => fields.extend([
+            ("event", "state".into()),
+            ("unit", unit.as_str().into()),
+            ("state", state.to_string().into()),
+            ("pid", (*pid).into()),
+            ("error", error.clone().into()),
+            ("exit_status", (*exit_status).into()),
+        ]),
+        EventKind::Restarted {
+            unit,
+            restarts,
+            failed,
+        } => fields.extend([
+            ("event", "restart".into()),
+            ("unit", unit.as_str().into()),
+            ("restarts", (*restarts).into()),
+            ("failed", (*failed).into()),
+        ]),
+        EventKind::Power(op) => {
+            fields.extend([("event", "power".into()), ("op", op.to_string().into())])
+        }
+        EventKind::Reload => fields.push(("event", "reload".into())),
+    }
+
+    Json::Object(fields)
+}
+
+fn describe(kind: &EventKind) -> String {
+    match kind {
+        EventKind::State {
+            unit,
+            state,
+            pid,
+            error,
+            exit_status,
+        } => {
+            let mut line = format!("{} {}", unit, state);
+
+            if let Some(pid) = pid {
+                line.push_str(&format!(" (pid {})", pid));
+            }
+
+            if let Some(code) = exit_status {
+                line.push_str(&format!(" (exit {})", code));
+            }
+
+            if let Some(error) = error {
+                line.push_str(&format!(": {}", error));
+            }
+
+            line
+        }
+        EventKind::Restarted {
+            unit,
+            restarts,
+            failed: true,
+        } => format!("{} failed after {} restarts", unit, restarts),
+        EventKind::Restarted { unit, restarts, .. } => {
+            format!("{} restarted ({} restarts)", unit, restarts)
+        }
+        EventKind::Power(op) => format!("system {}", op),
+        EventKind::Reload => "database reloaded".to_string(),
+    }
+}
+
+pub fn monitor(opts: Monitor) -> Result<()> {
+    for event in subscribe(opts.unit, opts.replay)? {
+        let event = event?;
+
+        if opts.json {
+            println!("{}", event_json(&event));
+        } else {
+            // UTC, the time zone database isn't available to kanit
+            let secs = event.time % 86400;
+
+            println!(
+                "{:02}:{:02}:{:02} {}",
+                secs / 3600,
+                secs % 3600 / 60,
+                secs % 60,
+                describe(&event.kind)
+            );
+        }
+    }
+
+    Ok(())
+}
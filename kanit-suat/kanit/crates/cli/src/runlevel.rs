@@ -0,0 +1,20 @@
+use nix::unistd::getuid;
+
+use kanit_common::error::{Result, StaticError};
+use kanit_common::level::parse_level;
+use kanit_control::{call, Request};
+
+use crate::flags::Runlevel;
+
+pub fn runlevel(opts: Runlevel) -> Result<()> {
+    if !getuid().is_root() {
+        Err(StaticError("operation not permitted"))?;
+    }
+
+    // validated here so mistakes are reported to the caller rather than init
+    let level = parse_level(&opts.level)?;
+
+    call(&Request::Runlevel { level })?;
+
+    Ok(())
+}
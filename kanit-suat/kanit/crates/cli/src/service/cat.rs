@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::PathBuf;
+
+use toml::Table;
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result, StaticError};
+use kanit_rc::db::dropin::{self, Merged};
+use kanit_unit::{UnitName, UnitNameExt};
+use kanit_units::baked_units;
+
+use crate::flags::Cat;
+
+pub fn cat(opts: Cat) -> Result<()> {
+    let name = UnitName::from(opts.unit.trim_end_matches(".toml"));
+
+    let mut path = PathBuf::from(constants::KAN_UNIT_DIR);
+
+    path.push(format!("{}.toml", name.template().unwrap_or(name.clone())));
+
+    let (base, source) = if path.exists() {
+        let contents = fs::read_to_string(&path).context("failed to read unit")?;
+
+        (
+            contents.parse::<Table>().context("failed to parse")?,
+            path.to_string_lossy().to_string(),
+        )
+    } else if let Some(unit) = baked_units().iter().find(|u| u.borrow().name() == name) {
+        (dropin::baked_table(&*unit.borrow()), "built-in".to_string())
+    } else {
+        Err(StaticError("failed to find unit"))?
+    };
+
+    println!("# {}\n{}", source, base);
+
+    let mut merged = Merged::new(base, &source);
+
+    for fragment in dropin::fragments(&name)? {
+        println!("# {}\n{}", fragment.source, fragment.table);
+
+        merged.merge(fragment);
+    }
+
+    println!("# effective");
+
+    for (key, value) in merged.table.iter() {
+        // unwrap: every key of the merged table has an origin
+        let origins = merged.origins.get(key).unwrap();
+
+        println!("{} = {} # {}", key, value, origins.join(", "));
+    }
+
+    Ok(())
+}
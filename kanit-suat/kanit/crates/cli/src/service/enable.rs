@@ -5,10 +5,13 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use atomic_write_file::AtomicWriteFile;
+use toml::{Table, Value};
 
 use kanit_common::constants;
 use kanit_common::error::{Context, Result, StaticError, WithError};
-use kanit_rc::db::{Database, DbUnit};
+use kanit_rc::db::dropin;
+use kanit_rc::db::{Database, DbUnit, UnitKind};
+use kanit_unit::{UnitName, UnitNameExt};
 
 use crate::flags::Enable;
 
@@ -19,13 +22,38 @@ pub fn enable(opts: Enable) -> Result<()> {
         Err(StaticError("failed to find kanit database"))?;
     }
 
+    let name = UnitName::from(opts.unit.trim_end_matches(".toml"));
+    let template = name.template();
+
     let mut unit = PathBuf::from(constants::KAN_UNIT_DIR);
 
-    unit.push(opts.unit);
+    // instances are read from their template, `foo@bar` from `foo@.toml`
+    if let Some(ref template) = template {
+        unit.push(format!("{}.toml", template));
+    } else {
+        unit.push(opts.unit);
+    }
+
+    let unit_contents = fs::read_to_string(&unit).context("failed to read unit")?;
+
+    // drop-ins aren't stored, they're applied whenever the unit is loaded
+    let mut unit_data: DbUnit = unit_contents
+        .parse::<Table>()
+        .and_then(|t| Value::Table(t).try_into())
+        .context("failed to parse")?;
 
-    let unit_contents = fs::read_to_string(unit).context("failed to read unit")?;
+    unit_data.source = Some(unit.to_string_lossy().to_string());
 
-    let unit_data: DbUnit = toml::from_str(&unit_contents).context("failed to parse")?;
+    if template.is_some() {
+        unit_data.instantiate(name)?;
+    }
+
+    let effective = dropin::apply(&unit_data)?;
+    let effective = effective.as_ref().unwrap_or(&unit_data);
+
+    if effective.kind != UnitKind::Target && effective.supervisor_opts.cmd.is_empty() {
+        Err(StaticError("only targets can be without a command"))?;
+    }
 
     let db_data = fs::read(db_path).context("failed to read database")?;
 
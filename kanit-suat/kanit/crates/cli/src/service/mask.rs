@@ -0,0 +1,66 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use atomic_write_file::AtomicWriteFile;
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result, StaticError};
+use kanit_control::{call, Request};
+use kanit_rc::db::Database;
+use kanit_unit::UnitName;
+
+use crate::flags::{Mask, Unmask};
+
+fn set_masked(unit: &str, masked: bool) -> Result<()> {
+    let db_path = Path::new(constants::KAN_DB);
+
+    if !db_path.exists() {
+        Err(StaticError("failed to find kanit database"))?;
+    }
+
+    let db_data = fs::read(db_path).context("failed to read database")?;
+
+    let mut db = Database::load(&db_data)?;
+
+    let unit_name = UnitName::from(unit);
+
+    // baked units are in the database as well
+    if !db.unit_infos.contains_key(&unit_name) {
+        Err(StaticError("failed to find unit in database"))?;
+    }
+
+    if masked {
+        if !db.masked.insert(unit_name) {
+            Err(StaticError("unit already masked"))?;
+        }
+    } else if !db.masked.remove(&unit_name) {
+        Err(StaticError("unit was not masked"))?;
+    }
+
+    // fails if an enabled unit needs the masked unit
+    db.rebuild_levels()?;
+
+    let new_db_data = db.dump()?;
+
+    let mut db_handle = AtomicWriteFile::open(db_path).context("failed to open database")?;
+
+    db_handle.write_all(&new_db_data)?;
+
+    db_handle.commit().context("failed to commit database")?;
+
+    // the socket only exists while init is running
+    if Path::new(constants::KAN_SOCKET).exists() {
+        call(&Request::Reload)?;
+    }
+
+    Ok(())
+}
+
+pub fn mask(opts: Mask) -> Result<()> {
+    set_masked(&opts.unit, true)
+}
+
+pub fn unmask(opts: Unmask) -> Result<()> {
+    set_masked(&opts.unit, false)
+}
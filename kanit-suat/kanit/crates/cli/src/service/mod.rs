@@ -1,15 +1,32 @@
 #[cfg(any(feature = "rkyv", feature = "postcard"))]
+pub use cat::cat;
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
 pub use disable::disable;
 #[cfg(any(feature = "rkyv", feature = "postcard"))]
 pub use enable::enable;
 #[cfg(any(feature = "rkyv", feature = "postcard"))]
 pub use list::list;
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
+pub use mask::{mask, unmask};
+pub use modify::{restart, start, stop};
+pub use reset::reset;
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
+pub use show::show;
 
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
+mod cat;
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
 mod disable;
 #[cfg(any(feature = "rkyv", feature = "postcard"))]
 mod enable;
 #[cfg(any(feature = "rkyv", feature = "postcard"))]
 mod list;
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
+mod mask;
+mod modify;
+mod reset;
+#[cfg(any(feature = "rkyv", feature = "postcard"))]
+mod show;
 
 #[cfg(not(any(feature = "rkyv", feature = "postcard")))]
 compile_error!("feature `postcard` or `rkyv` is needed to compile with feature `service`");
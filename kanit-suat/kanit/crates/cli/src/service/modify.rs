@@ -0,0 +1,44 @@
+use nix::unistd::getuid;
+
+use kanit_common::error::{Result, StaticError};
+use kanit_control::{call, Request};
+
+use crate::flags::{Restart, Start, Stop};
+
+/// Level units are started at when none is given, the same as `enable`.
+const DEFAULT_LEVEL: usize = 1;
+
+fn send(request: Request) -> Result<()> {
+    if !getuid().is_root() {
+        Err(StaticError("operation not permitted"))?;
+    }
+
+    // error responses carry the reason the operation failed
+    call(&request)?;
+
+    Ok(())
+}
+
+pub fn start(opts: Start) -> Result<()> {
+    send(Request::Start {
+        unit: opts.unit,
+        level: opts.level.unwrap_or(DEFAULT_LEVEL),
+        block: !opts.no_block,
+    })
+}
+
+pub fn stop(opts: Stop) -> Result<()> {
+    send(Request::Stop {
+        unit: opts.unit,
+        level: opts.level.unwrap_or(DEFAULT_LEVEL),
+        block: !opts.no_block,
+    })
+}
+
+pub fn restart(opts: Restart) -> Result<()> {
+    send(Request::Restart {
+        unit: opts.unit,
+        level: opts.level.unwrap_or(DEFAULT_LEVEL),
+        block: !opts.no_block,
+    })
+}
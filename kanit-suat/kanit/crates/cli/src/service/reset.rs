@@ -0,0 +1,25 @@
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+use kanit_common::error::{Context, Result, StaticError};
+use kanit_supervisor::UnitState;
+
+use crate::flags::Reset;
+
+pub fn reset(opts: Reset) -> Result<()> {
+    let state = UnitState::read(&opts.unit)?;
+
+    if !state.failed {
+        Err(StaticError("unit has not failed"))?;
+    }
+
+    // the supervisor may have been killed before clearing its state and its PID reused
+    let supervisor = state
+        .running_supervisor()
+        .context("failed to find supervisor")?;
+
+    // the supervisor restarts the unit once reset
+    kill(Pid::from_raw(supervisor as i32), Signal::SIGUSR1).context("failed to reset unit")?;
+
+    Ok(())
+}
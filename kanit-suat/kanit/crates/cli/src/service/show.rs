@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::Path;
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result, StaticError};
+use kanit_rc::db::Database;
+use kanit_supervisor::{Exit, UnitState};
+use kanit_unit::UnitName;
+use kanit_units::baked_units;
+
+use crate::flags::Show;
+use crate::json::Json;
+use crate::status::{format_duration, now, query, unit_json};
+
+/// Lines of each log file shown.
+const LOG_LINES: usize = 10;
+
+fn tail(path: &str) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return vec![];
+    };
+
+    let lines = contents.lines().collect::<Vec<_>>();
+
+    lines[lines.len().saturating_sub(LOG_LINES)..]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+fn names(names: &[UnitName]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+pub fn show(opts: Show) -> Result<()> {
+    let db_path = Path::new(constants::KAN_DB);
+
+    if !db_path.exists() {
+        Err(StaticError("failed to find kanit database"))?;
+    }
+
+    let db = Database::load(&fs::read(db_path).context("failed to read database")?)?;
+
+    let name = UnitName::from(opts.unit.as_str());
+
+    let info = db
+        .unit_infos
+        .get(&name)
+        .context("failed to find unit in database")?;
+
+    let status = query(Some(opts.unit.clone()))?;
+    let unit = status.units.first().context("failed to find unit")?;
+
+    let db_unit = db.units.get(&name);
+
+    let description = match db_unit {
+        Some(u) => u.description.as_ref().map(|d| d.to_string()),
+        None => baked_units()
+            .iter()
+            .find(|u| u.borrow().name() == name)
+            .and_then(|u| u.borrow().description().map(String::from)),
+    };
+
+    // only supervised units have a state file
+    let supervised = UnitState::read(&opts.unit).ok();
+
+    let restarts = supervised.as_ref().map_or(0, |s| s.restarts);
+    let last_exit = supervised
+        .as_ref()
+        .and_then(|s| s.exits.back())
+        .map(|e| match e.exit {
+            Exit::Code(code) => code,
+            Exit::Signal(sig) => 128 + sig,
+        })
+        .or(unit.exit_status);
+    let pid = supervised
+        .as_ref()
+        .filter(|s| s.running_supervisor().is_some())
+        .and_then(|s| s.pid)
+        .or(unit.pid);
+
+    let mut logs = vec![];
+
+    if let Some(u) = db_unit {
+        let opts = &u.supervisor_opts;
+
+        for path in [&opts.stdout, &opts.stderr].into_iter().flatten() {
+            if !logs.iter().any(|(p, _)| p == path) {
+                logs.push((path.clone(), tail(path)));
+            }
+        }
+    }
+
+    let deps = &info.dependencies;
+    let dependencies = [
+        ("needs", &deps.needs),
+        ("uses", &deps.uses),
+        ("wants", &deps.wants),
+        ("before", &deps.before),
+        ("after", &deps.after),
+        ("conflicts", &deps.conflicts),
+        ("provides", &deps.provides),
+    ];
+
+    if opts.json {
+        let mut fields = unit_json(unit);
+
+        fields.retain(|(k, _)| !matches!(*k, "pid" | "exit_status"));
+        fields.extend([
+            ("description", description.into()),
+            ("pid", pid.into()),
+            ("restarts", restarts.into()),
+            ("exit_status", last_exit.into()),
+            (
+                "dependencies",
+                Json::Object(
+                    dependencies
+                        .iter()
+                        .map(|(k, v)| (*k, names(v).into()))
+                        .collect(),
+                ),
+            ),
+            (
+                "logs",
+                Json::Array(
+                    logs.into_iter()
+                        .map(|(path, lines)| {
+                            Json::Object(vec![("path", path.into()), ("lines", lines.into())])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]);
+
+        println!("{}", Json::Object(fields));
+
+        return Ok(());
+    }
+
+    match description {
+        Some(description) => println!("{} - {}", unit.name, description),
+        None => println!("{}", unit.name),
+    }
+
+    println!("  level: {}", unit.level);
+    println!(
+        "  state: {} for {}",
+        unit.state,
+        format_duration(now().saturating_sub(unit.since))
+    );
+
+    if let Some(error) = &unit.error {
+        println!("  error: {}", error);
+    }
+
+    if let Some(pid) = pid {
+        println!("  pid: {}", pid);
+    }
+
+    if supervised.is_some() {
+        println!("  restarts: {}", restarts);
+    }
+
+    if let Some(code) = last_exit {
+        println!("  last exit: {}", code);
+    }
+
+    for (key, deps) in dependencies.iter().filter(|(_, d)| !d.is_empty()) {
+        println!("  {}: {}", key, names(deps).join(", "));
+    }
+
+    for (path, lines) in logs {
+        println!("\n# {}", path);
+
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kanit_common::error::{Context, Result};
+use kanit_control::{call, ActiveState, Request, Response, Status, UnitStatus};
+
+use crate::flags;
+use crate::json::Json;
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+pub fn query(unit: Option<String>) -> Result<Status> {
+    match call(&Request::Status { unit })? {
+        Response::Status(status) => Ok(status),
+        _ => None.context("unexpected response from init"),
+    }
+}
+
+pub fn unit_json(unit: &UnitStatus) -> Vec<(&'static str, Json)> {
+    vec![
+        ("name", unit.name.as_str().into()),
+        ("level", unit.level.into()),
+        ("state", unit.state.to_string().into()),
+        ("since", unit.since.into()),
+        ("pid", unit.pid.into()),
+        ("error", unit.error.clone().into()),
+        ("exit_status", unit.exit_status.into()),
+    ]
+}
+
+pub fn status(opts: flags::Status) -> Result<()> {
+    let status = query(None)?;
+
+    let failed = status
+        .units
+        .iter()
+        .filter(|u| u.state == ActiveState::Failed)
+        .count();
+
+    if opts.json {
+        let units = status
+            .units
+            .iter()
+            .map(|u| Json::Object(unit_json(u)))
+            .collect::<Vec<_>>();
+
+        println!(
+            "{}",
+            Json::Object(vec![
+                ("level", status.level.into()),
+                ("units", Json::Array(units)),
+                ("failed", failed.into()),
+            ])
+        );
+
+        return Ok(());
+    }
+
+    let width = status
+        .units
+        .iter()
+        .map(|u| u.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+
+    match status.level {
+        Some(level) => println!("level {}", level),
+        None => println!("not booted"),
+    }
+
+    println!(
+        "{:width$}  {:5}  {:12}  {:7}  SINCE",
+        "UNIT", "LEVEL", "STATE", "PID"
+    );
+
+    let now = now();
+
+    for unit in status.units.iter() {
+        println!(
+            "{:width$}  {:5}  {:12}  {:7}  {} ago",
+            unit.name,
+            unit.level,
+            unit.state.to_string(),
+            unit.pid.map_or("-".to_string(), |p| p.to_string()),
+            format_duration(now.saturating_sub(unit.since)),
+        );
+    }
+
+    println!("{} units, {} failed", status.units.len(), failed);
+
+    Ok(())
+}
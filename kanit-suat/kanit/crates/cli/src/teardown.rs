@@ -1,10 +1,8 @@
-use std::fs::write;
-
 use nix::sys::reboot::{reboot, RebootMode};
 use nix::unistd::getuid;
 
-use kanit_common::constants::KAN_PIPE;
 use kanit_common::error::{Context, Result, StaticError};
+use kanit_control::{call, PowerOp, Request};
 
 pub fn teardown(cmd: &str, force: bool) -> Result<()> {
     if !getuid().is_root() {
@@ -22,7 +20,15 @@ pub fn teardown(cmd: &str, force: bool) -> Result<()> {
         .context("Failed to reboot")?;
     }
 
-    write(KAN_PIPE, cmd).context("failed to write to pipe")?;
+    let op = match cmd {
+        "poweroff" => PowerOp::Poweroff,
+        "reboot" => PowerOp::Reboot,
+        "halt" => PowerOp::Halt,
+        "kexec" => PowerOp::Kexec,
+        _ => unreachable!(),
+    };
+
+    call(&Request::Power(op))?;
 
     Ok(())
 }
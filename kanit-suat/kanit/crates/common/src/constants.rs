@@ -1,7 +1,10 @@
-pub const KAN_PIPE: &str = "/run/kan.pipe";
+pub const KAN_SOCKET: &str = "/run/kan.sock";
 pub const KAN_TIMINGS: &str = "/run/kan.timing";
 pub const KAN_PATH: &str = "/bin:/sbin:/usr/bin:/usr/sbin";
 pub const KAN_SEED: &str = "/var/lib/seed";
 pub const KAN_DB: &str = "/var/lib/kan.db";
 pub const KAN_UNIT_DIR: &str = "/etc/kanit/";
+pub const KAN_ENVIRONMENT: &str = "/etc/kanit/environment";
+pub const KAN_BOOTED: &str = "/var/lib/kan.booted";
+pub const KAN_UNIT_STATE_DIR: &str = "/run/kanit/units";
 pub const KAN_VERSION: &str = "0.1.0";
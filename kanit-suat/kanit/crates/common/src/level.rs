@@ -0,0 +1,20 @@
+use crate::error::{Context, Result};
+
+/// Named levels and the index they map onto.
+/// Levels are cumulative, so a level includes every level below it.
+/// The baked units bring up `sysinit` and `boot` together in level 0.
+pub const NAMED_LEVELS: [(&str, usize); 4] =
+    [("sysinit", 0), ("boot", 0), ("single", 0), ("default", 1)];
+
+/// Parses a level by name or index. `shutdown` stops every level and maps onto `None`.
+pub fn parse_level(level: &str) -> Result<Option<usize>> {
+    if level == "shutdown" {
+        return Ok(None);
+    }
+
+    if let Some((_, i)) = NAMED_LEVELS.iter().find(|(name, _)| *name == level) {
+        return Ok(Some(*i));
+    }
+
+    level.parse::<usize>().map(Some).context("unknown level")
+}
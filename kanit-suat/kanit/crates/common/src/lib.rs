@@ -1,2 +1,3 @@
 pub mod constants;
 pub mod error;
+pub mod level;
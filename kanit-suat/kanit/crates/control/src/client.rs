@@ -0,0 +1,39 @@
+use std::os::unix::net::UnixStream;
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result};
+
+use crate::{read_frame, write_frame, Event, Request, Response};
+
+/// Sends a request to init and waits for its response, turning error responses into errors.
+pub fn call(request: &Request) -> Result<Response> {
+    let mut stream =
+        UnixStream::connect(constants::KAN_SOCKET).context("failed to connect to init")?;
+
+    write_frame(&mut stream, &request.encode()?)?;
+
+    Response::decode(&read_frame(&mut stream)?)?.into_result()
+}
+
+/// Events streamed by init after a subscription.
+pub struct Subscription(UnixStream);
+
+impl Iterator for Subscription {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(read_frame(&mut self.0).and_then(|payload| Event::decode(&payload)))
+    }
+}
+
+/// Subscribes to the events of a unit, or of every unit if none is given.
+pub fn subscribe(unit: Option<String>, replay: bool) -> Result<Subscription> {
+    let mut stream =
+        UnixStream::connect(constants::KAN_SOCKET).context("failed to connect to init")?;
+
+    write_frame(&mut stream, &Request::Subscribe { unit, replay }.encode()?)?;
+
+    Response::decode(&read_frame(&mut stream)?)?.into_result()?;
+
+    Ok(Subscription(stream))
+}
@@ -0,0 +1,103 @@
+use std::io::{Read, Write};
+
+use kanit_common::error::{Context, Result, StaticError, WithError};
+
+/// Bumped whenever a request or response changes shape.
+pub const PROTOCOL_VERSION: u16 = 1;
+/// The version followed by the length of the payload, both little endian.
+pub const HEADER_LEN: usize = 6;
+/// Requests are small, anything larger is a misbehaving peer.
+pub const MAX_FRAME: usize = 4 * 1024;
+/// Responses list every unit, but only come from init.
+pub const MAX_RESPONSE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub len: usize,
+}
+
+impl Header {
+    pub fn new(len: usize) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            len,
+        }
+    }
+
+    /// Parses a header, rejecting payloads longer than `max` before anything is allocated for them.
+    pub fn parse(bytes: [u8; HEADER_LEN], max: usize) -> Result<Self> {
+        let version = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let len = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]) as usize;
+
+        if len > max {
+            Err(StaticError("frame too large"))?;
+        }
+
+        Ok(Self { version, len })
+    }
+
+    pub fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+
+        bytes[..2].copy_from_slice(&self.version.to_le_bytes());
+        bytes[2..].copy_from_slice(&(self.len as u32).to_le_bytes());
+
+        bytes
+    }
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<()> {
+    writer
+        .write_all(&Header::new(payload.len()).to_bytes())
+        .context("failed to write header")?;
+    writer
+        .write_all(payload)
+        .context("failed to write payload")?;
+
+    Ok(())
+}
+
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut header = [0; HEADER_LEN];
+
+    reader
+        .read_exact(&mut header)
+        .context("failed to read header")?;
+
+    let header = Header::parse(header, MAX_RESPONSE)?;
+
+    if header.version != PROTOCOL_VERSION {
+        let version = header.version;
+
+        Err(WithError::with(move || {
+            format!("unsupported protocol version {}", version)
+        }))?;
+    }
+
+    let mut payload = vec![0; header.len];
+
+    reader
+        .read_exact(&mut payload)
+        .context("failed to read payload")?;
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header::new(MAX_FRAME);
+
+        assert_eq!(Header::parse(header.to_bytes(), MAX_FRAME).unwrap(), header);
+    }
+
+    #[test]
+    fn oversized_frame() {
+        assert!(Header::parse(Header::new(MAX_FRAME + 1).to_bytes(), MAX_FRAME).is_err());
+        assert!(Header::parse([1, 0, 0xff, 0xff, 0xff, 0xff], MAX_RESPONSE).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+//! Requests sent to init over its control socket and the responses to them.
+//!
+//! Each message is framed by the protocol version and the length of its payload,
+//! encoded the same way as the database. Postcard is used unless rkyv is selected.
+
+use std::error;
+use std::fmt::{self, Display, Formatter};
+
+#[cfg(all(feature = "postcard", not(feature = "rkyv")))]
+use postcard::{from_bytes, to_stdvec};
+#[cfg(feature = "rkyv")]
+use rkyv::ser::serializers::AllocSerializer;
+#[cfg(feature = "rkyv")]
+use rkyv::ser::Serializer;
+#[cfg(feature = "rkyv")]
+use rkyv::{from_bytes, AlignedVec, Archive, Deserialize, Serialize};
+#[cfg(all(feature = "postcard", not(feature = "rkyv")))]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "rkyv")]
+use kanit_common::error::StaticError;
+use kanit_common::error::{Context, Result};
+
+pub use client::{call, subscribe, Subscription};
+pub use frame::{
+    read_frame, write_frame, Header, HEADER_LEN, MAX_FRAME, MAX_RESPONSE, PROTOCOL_VERSION,
+};
+
+mod client;
+mod frame;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum PowerOp {
+    Poweroff,
+    Reboot,
+    Halt,
+    Kexec,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum Request {
+    Start {
+        unit: String,
+        level: usize,
+        /// Whether the response waits for the operation to finish.
+        block: bool,
+    },
+    Stop {
+        unit: String,
+        level: usize,
+        /// Whether the response waits for the operation to finish.
+        block: bool,
+    },
+    Restart {
+        unit: String,
+        level: usize,
+        /// Whether the response waits for the operation to finish.
+        block: bool,
+    },
+    /// The status of a unit, or of every unit if none is given.
+    Status { unit: Option<String> },
+    /// Reloads the database from disk.
+    Reload,
+    /// Switches to a level, `None` stops every level.
+    Runlevel { level: Option<usize> },
+    /// Tears down the system before powering it off.
+    Power(PowerOp),
+    /// Streams events of a unit, or of every unit if none is given, after the response.
+    /// Recent events are sent first if `replay` is set.
+    Subscribe { unit: Option<String>, replay: bool },
+}
+
+impl Request {
+    /// Whether the request can be made by users other than root.
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, Self::Status { .. } | Self::Subscribe { .. })
+    }
+
+    /// Whether the caller waits for the request to be handled before being answered.
+    pub fn is_blocking(&self) -> bool {
+        !matches!(
+            self,
+            Self::Start { block: false, .. }
+                | Self::Stop { block: false, .. }
+                | Self::Restart { block: false, .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum ActiveState {
+    Inactive,
+    Activating,
+    Active,
+    Deactivating,
+    Failed,
+    /// The unit's conditions or needs weren't met.
+    Skipped,
+}
+
+impl Display for PowerOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poweroff => write!(f, "poweroff"),
+            Self::Reboot => write!(f, "reboot"),
+            Self::Halt => write!(f, "halt"),
+            Self::Kexec => write!(f, "kexec"),
+        }
+    }
+}
+
+impl Display for ActiveState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inactive => write!(f, "inactive"),
+            Self::Activating => write!(f, "activating"),
+            Self::Active => write!(f, "active"),
+            Self::Deactivating => write!(f, "deactivating"),
+            Self::Failed => write!(f, "failed"),
+            Self::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct UnitStatus {
+    pub name: String,
+    pub level: usize,
+    pub state: ActiveState,
+    /// Seconds since the unix epoch the unit entered its state.
+    pub since: u64,
+    pub pid: Option<u32>,
+    /// Why the unit failed or was skipped.
+    pub error: Option<String>,
+    /// The exit code of the unit's process, or 128 plus the signal which killed it.
+    pub exit_status: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct Status {
+    /// The level the system is at, `None` until booted or once shut down.
+    pub level: Option<usize>,
+    pub units: Vec<UnitStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum ControlErrorKind {
+    /// The caller isn't allowed to make the request.
+    Denied,
+    /// The caller speaks a different version of the protocol.
+    Version,
+    /// The request couldn't be decoded.
+    Invalid,
+    /// The request was understood but failed.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct ControlError {
+    pub kind: ControlErrorKind,
+    pub message: String,
+}
+
+impl ControlError {
+    pub fn new<S: ToString>(kind: ControlErrorKind, message: S) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for ControlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for ControlError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum Response {
+    Ok,
+    Status(Status),
+    Error(ControlError),
+}
+
+impl Response {
+    /// Turns an error response into an error.
+    pub fn into_result(self) -> Result<Self> {
+        match self {
+            Self::Error(e) => Err(e)?,
+            res => Ok(res),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum EventKind {
+    /// A unit changed state.
+    State {
+        unit: String,
+        state: ActiveState,
+        pid: Option<u32>,
+        error: Option<String>,
+        exit_status: Option<i32>,
+    },
+    /// The supervisor of a unit restarted it, or gave up once it hit its start limit.
+    Restarted {
+        unit: String,
+        restarts: u64,
+        failed: bool,
+    },
+    /// The system is being torn down.
+    Power(PowerOp),
+    /// The database was reloaded from disk.
+    Reload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "rkyv", derive(Archive))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct Event {
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    pub kind: EventKind,
+}
+
+impl Event {
+    /// The unit the event is about, `None` for events about the whole system.
+    pub fn unit(&self) -> Option<&str> {
+        match &self.kind {
+            EventKind::State { unit, .. } | EventKind::Restarted { unit, .. } => Some(unit),
+            EventKind::Power(_) | EventKind::Reload => None,
+        }
+    }
+}
+
+// archived data has to be aligned, which the frame header may have broken
+#[cfg(feature = "rkyv")]
+fn aligned(bytes: &[u8]) -> AlignedVec {
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    aligned
+}
+
+// the types can't be generic over rkyv's serializer without a pile of bounds
+macro_rules! codec {
+    ($ty:ty, $name:literal) => {
+        impl $ty {
+            #[cfg(feature = "rkyv")]
+            pub fn encode(&self) -> Result<Vec<u8>> {
+                let mut serializer = AllocSerializer::<256>::default();
+                serializer
+                    .serialize_value(self)
+                    .context(concat!("failed to serialize ", $name))?;
+
+                Ok(serializer.into_serializer().into_inner().to_vec())
+            }
+
+            #[cfg(feature = "rkyv")]
+            pub fn decode(bytes: &[u8]) -> Result<Self> {
+                Ok(from_bytes(&aligned(bytes))
+                    .map_err(|_| StaticError(concat!("failed to deserialize ", $name)))?)
+            }
+
+            #[cfg(all(feature = "postcard", not(feature = "rkyv")))]
+            pub fn encode(&self) -> Result<Vec<u8>> {
+                to_stdvec(self).context(concat!("failed to serialize ", $name))
+            }
+
+            #[cfg(all(feature = "postcard", not(feature = "rkyv")))]
+            pub fn decode(bytes: &[u8]) -> Result<Self> {
+                from_bytes(bytes).context(concat!("failed to deserialize ", $name))
+            }
+        }
+    };
+}
+
+codec!(Request, "request");
+codec!(Response, "response");
+codec!(Event, "event");
+
+#[cfg(not(any(feature = "rkyv", feature = "postcard")))]
+compile_error!("control requires feature 'rkyv' or 'postcard' selected");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unaligned_payload() {
+        let request = Request::Start {
+            unit: "syslog".to_string(),
+            level: 1,
+            block: true,
+        };
+
+        // payloads follow the header, so they can start at any offset
+        for offset in 0..8 {
+            let mut bytes = vec![0; offset];
+            bytes.extend(request.encode().unwrap());
+
+            assert_eq!(Request::decode(&bytes[offset..]).unwrap(), request);
+        }
+    }
+}
@@ -22,6 +22,8 @@ pub mod tap {
     pub fn ok<S: ToString>(_: usize, _: Option<S>) {}
 
     pub fn not_ok<S: ToString>(_: usize, _: Option<S>) {}
+
+    pub fn skip<S: ToString, R: ToString>(_: usize, _: Option<S>, _: R) {}
 }
 
 #[cfg(not(feature = "timings"))]
@@ -38,6 +40,8 @@ pub mod timing {
 
     pub fn pop_scope(_id: usize) {}
 
+    pub fn annotate<S: ToString>(_id: usize, _note: S) {}
+
     pub fn get_scopes() -> Rc<[Scope]> {
         Rc::from([])
     }
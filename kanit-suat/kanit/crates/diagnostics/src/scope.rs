@@ -6,4 +6,6 @@ pub struct Scope {
     pub start: Instant,
     pub duration: Option<Duration>,
     pub level: usize,
+    /// Why the scope ended early, such as the reason a unit was skipped.
+    pub note: Option<String>,
 }
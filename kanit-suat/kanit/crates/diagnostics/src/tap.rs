@@ -48,6 +48,19 @@ pub fn ok<S: ToString>(test: usize, desc: Option<S>) {
     }
 }
 
+pub fn skip<S: ToString, R: ToString>(test: usize, desc: Option<S>, reason: R) {
+    if let Some(desc) = desc {
+        print_leveled(format!(
+            "ok {} - {} # SKIP {}",
+            test,
+            desc.to_string(),
+            reason.to_string()
+        ));
+    } else {
+        print_leveled(format!("ok {} # SKIP {}", test, reason.to_string()));
+    }
+}
+
 pub fn not_ok<S: ToString>(test: usize, desc: Option<S>) {
     if let Some(desc) = desc {
         print_leveled(format!("not ok {} - {}", test, desc.to_string()));
@@ -31,6 +31,7 @@ pub fn push_scope<S: ToString>(name: S) -> Option<usize> {
             start: Instant::now(),
             duration: None,
             level,
+            note: None,
         });
 
         let id = timer.scopes.len() - 1;
@@ -57,6 +58,14 @@ pub fn pop_scope(id: Option<usize>) {
     }
 }
 
+pub fn annotate<S: ToString>(id: Option<usize>, note: S) {
+    if let Some(timer) = GLOBAL_TIMER.get() {
+        if let Some(id) = id {
+            timer.borrow_mut().scopes[id].note = Some(note.to_string());
+        }
+    }
+}
+
 pub fn get_scopes() -> Rc<[Scope]> {
     if let Some(timer) = GLOBAL_TIMER.get() {
         timer.borrow().scopes.clone().into()
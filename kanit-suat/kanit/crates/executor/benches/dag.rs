@@ -0,0 +1,95 @@
+// compares starting synthetic units group by group against starting them as their waits finish
+// run with `cargo bench -p kanit-executor`
+
+use std::time::{Duration, Instant};
+
+use async_io::Timer;
+
+use kanit_executor::{block, join_all, try_join_dag};
+
+struct SyntheticUnit {
+    group: usize,
+    duration: Duration,
+    waits: Vec<usize>,
+}
+
+// roughly a boot, a slow unit (such as `hwdrivers`) holds back units which don't depend on it
+fn synthetic_units() -> Vec<SyntheticUnit> {
+    let mut units = vec![];
+
+    for i in 0..16 {
+        units.push(SyntheticUnit {
+            group: 0,
+            duration: Duration::from_millis(if i == 0 { 200 } else { 5 + i as u64 }),
+            waits: vec![],
+        });
+    }
+
+    for i in 0..16 {
+        units.push(SyntheticUnit {
+            group: 1,
+            duration: Duration::from_millis(20),
+            // nothing waits on the slow unit
+            waits: vec![1 + i % 15],
+        });
+    }
+
+    for i in 0..16 {
+        units.push(SyntheticUnit {
+            group: 2,
+            duration: Duration::from_millis(10),
+            waits: vec![16 + i, 16 + (i + 1) % 16],
+        });
+    }
+
+    units
+}
+
+async fn run(duration: Duration) -> Result<(), ()> {
+    Timer::after(duration).await;
+    Ok(())
+}
+
+fn barriers(units: &[SyntheticUnit]) -> Duration {
+    let groups = units.iter().map(|u| u.group).max().unwrap_or(0) + 1;
+    let durations = (0..groups)
+        .map(|g| {
+            units
+                .iter()
+                .filter(|u| u.group == g)
+                .map(|u| u.duration)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+
+    block(async move {
+        for group in durations {
+            join_all(group.into_iter().map(run)).await;
+        }
+    });
+
+    start.elapsed()
+}
+
+fn dag(units: &[SyntheticUnit]) -> Duration {
+    let durations = units.iter().map(|u| u.duration).collect::<Vec<_>>();
+    let waits = units.iter().map(|u| u.waits.clone()).collect::<Vec<_>>();
+
+    let start = Instant::now();
+
+    block(async move {
+        let _ = try_join_dag(durations.into_iter().map(run), &waits).await;
+    });
+
+    start.elapsed()
+}
+
+fn main() {
+    let units = synthetic_units();
+
+    println!("{} synthetic units", units.len());
+    println!("group barriers: {:?}", barriers(&units));
+    println!("dag scheduler:  {:?}", dag(&units));
+}
@@ -1,11 +1,14 @@
 use std::future::Future;
 use std::sync::OnceLock;
 
+use async_channel::unbounded;
 use async_executor::{LocalExecutor, Task};
 use futures_lite::stream::iter;
 use futures_lite::StreamExt;
 use send_wrapper::SendWrapper;
 
+pub mod process;
+
 static GLOBAL_EXECUTOR: OnceLock<SendWrapper<LocalExecutor<'static>>> = OnceLock::new();
 
 pub fn spawn<T: 'static>(future: impl Future<Output = T> + 'static) -> Task<T> {
@@ -31,3 +34,62 @@ where
 
     iter(handles).then(|f| f).collect().await
 }
+
+/// Runs each future as soon as the futures it waits on have finished, `waits` holds the indices of
+/// those futures and must not form a cycle. Results are returned in order of completion, stopping
+/// at the first error.
+pub async fn try_join_dag<I, F, T: 'static, E: 'static>(
+    futures: I,
+    waits: &[Vec<usize>],
+) -> Result<Vec<T>, E>
+where
+    I: IntoIterator<Item = F>,
+    F: Future<Output = Result<T, E>> + 'static,
+{
+    let mut futures: Vec<_> = futures.into_iter().map(Some).collect();
+    let mut remaining: Vec<_> = waits.iter().map(|w| w.len()).collect();
+    let mut dependents = vec![vec![]; futures.len()];
+
+    for (i, wait) in waits.iter().enumerate() {
+        for j in wait {
+            dependents[*j].push(i);
+        }
+    }
+
+    let (tx, rx) = unbounded();
+    let mut tasks = vec![];
+
+    let mut launch = |i: usize, tasks: &mut Vec<Task<()>>| {
+        // unwrap: a future is only launched once all its waits finished, which happens once
+        let future = futures[i].take().unwrap();
+        let tx = tx.clone();
+
+        tasks.push(spawn(async move {
+            let _ = tx.send((i, future.await)).await;
+        }));
+    };
+
+    for (i, _) in remaining.iter().enumerate().filter(|(_, r)| **r == 0) {
+        launch(i, &mut tasks);
+    }
+
+    let mut results = vec![];
+
+    while results.len() < waits.len() {
+        // unwrap: the sender is held until all futures finished
+        let (i, result) = rx.recv().await.unwrap();
+
+        // dropping the remaining tasks cancels them
+        results.push(result?);
+
+        for j in dependents[i].iter() {
+            remaining[*j] -= 1;
+
+            if remaining[*j] == 0 {
+                launch(*j, &mut tasks);
+            }
+        }
+    }
+
+    Ok(results)
+}
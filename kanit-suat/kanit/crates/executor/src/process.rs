@@ -0,0 +1,263 @@
+//! Child processes reaped in one place.
+//!
+//! As PID 1, init inherits every orphan, so children are collected with `waitpid(-1)` by [`reap`]
+//! rather than per child. Statuses of spawned children are kept until they are waited on, while
+//! those of any other process are discarded.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::future::poll_fn;
+use std::io;
+use std::os::fd::AsFd;
+use std::os::unix::process::ExitStatusExt;
+use std::process;
+use std::task::{Poll, Waker};
+
+use async_io::Async;
+use async_signal::{Signal, Signals};
+use futures_lite::{future, AsyncRead, AsyncReadExt, StreamExt};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+
+pub use std::process::{ExitStatus, Output, Stdio};
+
+enum Entry {
+    Running(Vec<Waker>),
+    Exited(ExitStatus),
+}
+
+thread_local! {
+    static CHILDREN: RefCell<HashMap<u32, Entry>> = RefCell::new(HashMap::new());
+}
+
+/// Collects every exited child, waking those waiting on spawned ones.
+pub fn reap() {
+    loop {
+        let (pid, status) = match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(pid, code)) => (pid, ExitStatus::from_raw(code << 8)),
+            Ok(WaitStatus::Signaled(pid, signal, core)) => (
+                pid,
+                ExitStatus::from_raw(signal as i32 | if core { 0x80 } else { 0 }),
+            ),
+            // stopped and continued children are only reported when asked for
+            Ok(WaitStatus::StillAlive) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        CHILDREN.with_borrow_mut(|children| {
+            if let Some(Entry::Running(wakers)) = children.get_mut(&(pid.as_raw() as u32)) {
+                wakers.drain(..).for_each(Waker::wake);
+                children.insert(pid.as_raw() as u32, Entry::Exited(status));
+            }
+        });
+    }
+}
+
+/// Reaps children whenever `SIGCHLD` is received, for when nothing else listens for it.
+pub async fn driver() {
+    let mut signals = match Signals::new([Signal::Child]) {
+        Ok(signals) => signals,
+        Err(_) => return,
+    };
+
+    // children may have exited before the signal was registered
+    reap();
+
+    while signals.next().await.is_some() {
+        reap();
+    }
+}
+
+/// Takes the status of a spawned child if it has exited.
+pub fn try_wait(pid: u32) -> Option<ExitStatus> {
+    CHILDREN.with_borrow_mut(|children| match children.remove(&pid) {
+        Some(Entry::Exited(status)) => Some(status),
+        Some(entry) => {
+            children.insert(pid, entry);
+            None
+        }
+        None => None,
+    })
+}
+
+/// Waits for a spawned child to be reaped, returning `None` if its status was already taken.
+pub async fn wait(pid: u32) -> Option<ExitStatus> {
+    poll_fn(|cx| {
+        CHILDREN.with_borrow_mut(|children| match children.get_mut(&pid) {
+            Some(Entry::Running(wakers)) => {
+                if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+
+                Poll::Pending
+            }
+            Some(Entry::Exited(_)) => match children.remove(&pid) {
+                Some(Entry::Exited(status)) => Poll::Ready(Some(status)),
+                _ => unreachable!(),
+            },
+            None => Poll::Ready(None),
+        })
+    })
+    .await
+}
+
+pub struct Command(process::Command);
+
+impl Command {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        Self(process::Command::new(program))
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.0.arg(arg);
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(&mut self, args: I) -> &mut Self {
+        self.0.args(args);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.0.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.0.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.0.stderr(cfg);
+        self
+    }
+
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        let child = self.0.spawn()?;
+
+        // nothing is reaped in between as reaping happens on this thread
+        CHILDREN.with_borrow_mut(|children| children.insert(child.id(), Entry::Running(vec![])));
+
+        Ok(Child {
+            inner: child,
+            status: None,
+        })
+    }
+}
+
+impl From<process::Command> for Command {
+    fn from(cmd: process::Command) -> Self {
+        Self(cmd)
+    }
+}
+
+pub struct Child {
+    inner: process::Child,
+    status: Option<ExitStatus>,
+}
+
+async fn read_pipe<P: AsFd>(pipe: Option<P>) -> io::Result<Vec<u8>>
+where
+    Async<P>: AsyncRead + Unpin,
+{
+    let mut buf = vec![];
+
+    if let Some(pipe) = pipe {
+        Async::new(pipe)?.read_to_end(&mut buf).await?;
+    }
+
+    Ok(buf)
+}
+
+impl Child {
+    pub fn id(&self) -> u32 {
+        self.inner.id()
+    }
+
+    pub async fn status(&mut self) -> io::Result<ExitStatus> {
+        if let Some(status) = self.status {
+            return Ok(status);
+        }
+
+        let status = wait(self.id()).await.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "child was already waited on")
+        })?;
+
+        self.status = Some(status);
+
+        Ok(status)
+    }
+
+    pub async fn output(mut self) -> io::Result<Output> {
+        let (stdout, stderr) = future::zip(
+            read_pipe(self.inner.stdout.take()),
+            read_pipe(self.inner.stderr.take()),
+        )
+        .await;
+
+        Ok(Output {
+            status: self.status().await?,
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_io::Timer;
+
+    use super::*;
+
+    // children of parallel tests would be reaped by each other, so one test covers everything
+    #[test]
+    fn reaped_children() {
+        let reaper = async {
+            // nothing listens for SIGCHLD in tests
+            loop {
+                reap();
+                Timer::after(Duration::from_millis(10)).await;
+            }
+        };
+
+        let children = async {
+            let status = Command::new("sh")
+                .args(["-c", "exit 3"])
+                .spawn()
+                .unwrap()
+                .status()
+                .await
+                .unwrap();
+
+            assert_eq!(status.code(), Some(3));
+
+            let output = Command::new("sh")
+                .args(["-c", "echo out; echo err >&2"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap()
+                .output()
+                .await
+                .unwrap();
+
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"out\n");
+            assert_eq!(output.stderr, b"err\n");
+
+            let child = Command::new("sh")
+                .args(["-c", "kill -9 $$"])
+                .spawn()
+                .unwrap();
+
+            assert_eq!(wait(child.id()).await.unwrap().signal(), Some(9));
+            assert_eq!(wait(child.id()).await, None);
+            assert_eq!(try_wait(child.id()), None);
+        };
+
+        async_io::block_on(future::or(children, reaper));
+    }
+}
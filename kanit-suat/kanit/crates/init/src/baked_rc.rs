@@ -1,5 +1,10 @@
 use kanit_common::error::Result;
+#[cfg(not(feature = "testing"))]
+use kanit_control::{Request, Response};
 use kanit_executor::block;
+use kanit_rc::BootOptions;
+
+use crate::cmdline::KernelOptions;
 
 pub fn teardown_rc() -> Result<()> {
     block(kanit_rc::teardown())?;
@@ -7,14 +12,29 @@ pub fn teardown_rc() -> Result<()> {
     Ok(())
 }
 
-pub fn initialize_rc() -> Result<()> {
-    block(kanit_rc::start())?;
+pub fn initialize_rc(opts: &KernelOptions) -> Result<()> {
+    let boot = BootOptions {
+        level: opts.level,
+        mask: opts.mask.iter().map(|u| u.as_str().into()).collect(),
+    };
+
+    // boot transitions are kept for monitors asking for a replay
+    #[cfg(not(feature = "testing"))]
+    kanit_rc::observe(crate::monitor::publish);
+
+    block(kanit_rc::start(boot))?;
 
     Ok(())
 }
 
 #[inline]
 #[cfg(not(feature = "testing"))]
-pub async fn event_rc(ev: Vec<u8>) -> Result<()> {
-    kanit_rc::event(ev).await
+pub fn child_rc() -> Result<()> {
+    kanit_rc::reap()
+}
+
+#[inline]
+#[cfg(not(feature = "testing"))]
+pub async fn event_rc(request: Request) -> Result<Response> {
+    kanit_rc::event(request).await
 }
@@ -4,7 +4,7 @@ use std::backtrace::Backtrace;
 use std::io::{stdin, stdout, Write};
 use std::os::fd::RawFd;
 use std::os::unix::process::CommandExt;
-use std::panic::PanicInfo;
+use std::panic::PanicHookInfo;
 use std::process::Command;
 
 use nix::fcntl::{open, OFlag};
@@ -24,7 +24,7 @@ const VT_WAIT_ACTIVE: i32 = 0x5607;
 ioctl_write_int_bad!(vt_activate, VT_ACTIVATE);
 ioctl_write_int_bad!(vt_wait_activate, VT_WAIT_ACTIVE);
 
-fn print_info(info: &PanicInfo, tty: bool) {
+fn print_info(info: &PanicHookInfo, tty: bool) {
     if tty {
         print!("{}", BLUE_BG);
         print!("{}", WHITE_TEXT);
@@ -66,7 +66,7 @@ fn print_info(info: &PanicInfo, tty: bool) {
         let _ = stdin().read_line(&mut junk);
     }
 
-    Command::new("sh").exec();
+    let _ = Command::new("sh").exec();
 
     if tty {
         println!("failed to enter emergency shell, press enter to restart...");
@@ -78,7 +78,7 @@ fn print_info(info: &PanicInfo, tty: bool) {
     let _ = reboot(RebootMode::RB_AUTOBOOT);
 }
 
-pub fn bsod(info: &PanicInfo) {
+pub fn bsod(info: &PanicHookInfo) {
     if !isatty(RawFd::from(1)).unwrap_or(false) {
         return print_info(info, false);
     }
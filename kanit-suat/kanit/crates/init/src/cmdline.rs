@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::LevelFilter;
+use nix::mount::{mount, MsFlags};
+
+use kanit_common::level::parse_level;
+
+/// Boot overrides given on the kernel command line.
+#[derive(Default)]
+pub struct KernelOptions {
+    /// `kanit.level=N`, stops booting after level N.
+    pub level: Option<usize>,
+    /// `single` or `emergency`, drops to a rescue shell after level 0.
+    pub rescue: bool,
+    /// `kanit.mask=foo,bar`, units which are skipped.
+    pub mask: Vec<String>,
+    /// `kanit.log=debug` (or `kanit.debug`), level of the initialization logger.
+    pub log: Option<LevelFilter>,
+    /// `kanit.setenv=K=V`, environment variables passed to services.
+    pub setenv: Vec<(String, String)>,
+}
+
+impl KernelOptions {
+    pub fn parse(cmdline: &str) -> Self {
+        let mut opts = Self::default();
+
+        for word in cmdline.split_whitespace() {
+            let (key, value) = word.split_once('=').unwrap_or((word, ""));
+
+            match key {
+                "single" | "emergency" | "kanit.single" => opts.rescue = true,
+                "kanit.level" => opts.level = parse_level(value).ok().flatten(),
+                "kanit.mask" => opts
+                    .mask
+                    .extend(value.split(',').filter(|u| !u.is_empty()).map(String::from)),
+                "kanit.log" => opts.log = LevelFilter::from_str(value).ok(),
+                "kanit.debug" => opts.log = Some(LevelFilter::Debug),
+                "kanit.setenv" => {
+                    if let Some((k, v)) = value.split_once('=') {
+                        opts.setenv.push((k.to_string(), v.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if opts.rescue {
+            opts.level = Some(0);
+        }
+
+        opts
+    }
+
+    /// Reads the kernel command line, mounting `/proc` early if needed.
+    pub fn read() -> Self {
+        // `procfs` leaves an already mounted `/proc` alone
+        if !Path::new("/proc/cmdline").exists() {
+            let _ = mount(
+                Some("none"),
+                "/proc",
+                Some("proc"),
+                MsFlags::MS_NODEV | MsFlags::MS_NOEXEC | MsFlags::MS_NOSUID,
+                Some(""),
+            );
+        }
+
+        fs::read_to_string("/proc/cmdline")
+            .map(|c| Self::parse(&c))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_options() {
+        let opts = KernelOptions::parse(
+            "root=/dev/sda1 kanit.level=2 kanit.mask=foo,,bar kanit.mask=baz kanit.log=warn \
+             kanit.setenv=LANG=C.UTF-8",
+        );
+
+        assert_eq!(opts.level, Some(2));
+        assert!(!opts.rescue);
+        assert_eq!(opts.mask, ["foo", "bar", "baz"]);
+        assert_eq!(opts.log, Some(LevelFilter::Warn));
+        assert_eq!(opts.setenv, [("LANG".to_string(), "C.UTF-8".to_string())]);
+    }
+
+    #[test]
+    fn parse_level_names() {
+        assert_eq!(KernelOptions::parse("kanit.level=default").level, Some(1));
+        assert_eq!(KernelOptions::parse("kanit.level=nope").level, None);
+        assert_eq!(KernelOptions::parse("kanit.unit=2").level, None);
+    }
+
+    #[test]
+    fn parse_rescue() {
+        for cmdline in ["single", "emergency", "kanit.single kanit.level=3"] {
+            let opts = KernelOptions::parse(cmdline);
+
+            assert!(opts.rescue);
+            assert_eq!(opts.level, Some(0));
+        }
+    }
+
+    #[test]
+    fn parse_debug() {
+        assert_eq!(
+            KernelOptions::parse("kanit.debug").log,
+            Some(LevelFilter::Debug)
+        );
+        assert_eq!(KernelOptions::parse("quiet").log, None);
+    }
+}
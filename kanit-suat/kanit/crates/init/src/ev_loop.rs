@@ -1,37 +1,37 @@
-use async_fs::File;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::time::Duration;
+
+use async_io::{Async, Timer};
 use async_signal::{Signal, Signals};
-use futures_lite::{AsyncReadExt, StreamExt};
-use nix::errno::Errno;
+use futures_lite::{future, AsyncReadExt, AsyncWriteExt, StreamExt};
+use log::warn;
 use nix::sys::reboot::RebootMode;
-use nix::sys::stat::Mode;
-use nix::unistd::mkfifo;
+use nix::sys::socket::{getsockopt, sockopt};
 
 use kanit_common::constants;
 use kanit_common::error::{Context, Result};
+use kanit_control::{
+    ControlError, ControlErrorKind, EventKind, Header, PowerOp, Request, Response, HEADER_LEN,
+    MAX_FRAME, PROTOCOL_VERSION,
+};
 use kanit_executor::{block, spawn};
 
-use crate::{event_rc, teardown};
-
-async fn pipe_handler(data: Vec<u8>) -> Result<()> {
-    if data.starts_with(b"halt") {
-        teardown(Some(RebootMode::RB_HALT_SYSTEM))?;
-    } else if data.starts_with(b"poweroff") {
-        teardown(Some(RebootMode::RB_POWER_OFF))?;
-    } else if data.starts_with(b"reboot") {
-        teardown(Some(RebootMode::RB_AUTOBOOT))?;
-    } else if data.starts_with(b"kexec") {
-        teardown(Some(RebootMode::RB_KEXEC))?;
-    } else {
-        event_rc(data).await?;
-    }
+use crate::monitor::{self, watch_restarts};
+use crate::{child_rc, event_rc, teardown};
 
-    Ok(())
-}
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
 
 async fn listen_signal() -> Result<()> {
     let mut signals = Signals::new([Signal::Int, Signal::Term, Signal::Child])
         .context("failed to register signals")?;
 
+    // children which exited during boot were not signalled to this listener
+    if let Err(e) = child_rc() {
+        warn!("{}", e);
+    }
+
     while let Some(signal) = signals.next().await {
         let signal = if let Ok(signal) = signal {
             signal
@@ -39,40 +39,210 @@ async fn listen_signal() -> Result<()> {
             continue;
         };
 
-        println!("*boop* {}", signal as i32);
+        if signal == Signal::Child {
+            if let Err(e) = child_rc() {
+                warn!("{}", e);
+            }
+        } else {
+            println!("*boop* {}", signal as i32);
+        }
     }
 
     Ok(())
 }
 
-async fn listen_file() -> Result<()> {
-    mkfifo(constants::KAN_PIPE, Mode::S_IRUSR | Mode::S_IWUSR).context("failed to create pipe")?;
+async fn read_frame(stream: &mut Async<UnixStream>) -> Result<Request, ControlError> {
+    let mut header = [0; HEADER_LEN];
+
+    stream
+        .read_exact(&mut header)
+        .await
+        .map_err(|e| ControlError::new(ControlErrorKind::Invalid, e))?;
+
+    // anyone can connect, so the length is checked before the payload is allocated
+    let header = Header::parse(header, MAX_FRAME)
+        .map_err(|e| ControlError::new(ControlErrorKind::Invalid, e))?;
+
+    if header.version != PROTOCOL_VERSION {
+        Err(ControlError::new(
+            ControlErrorKind::Version,
+            format!(
+                "unsupported protocol version {}, expected {}",
+                header.version, PROTOCOL_VERSION
+            ),
+        ))?;
+    }
+
+    let mut payload = vec![0; header.len];
+
+    stream
+        .read_exact(&mut payload)
+        .await
+        .map_err(|e| ControlError::new(ControlErrorKind::Invalid, e))?;
+
+    Request::decode(&payload).map_err(|e| ControlError::new(ControlErrorKind::Invalid, e))
+}
+
+async fn read_request(stream: &mut Async<UnixStream>) -> Result<Request, ControlError> {
+    // a caller that never finishes its request would otherwise hold the connection forever
+    future::or(read_frame(stream), async {
+        Timer::after(REQUEST_TIMEOUT).await;
+
+        Err(ControlError::new(
+            ControlErrorKind::Invalid,
+            "timed out reading request",
+        ))
+    })
+    .await
+}
+
+async fn handle_request(request: Request) -> Response {
+    let reload = request == Request::Reload;
+
+    match event_rc(request).await {
+        Ok(response) => {
+            if reload {
+                monitor::publish(EventKind::Reload);
+            }
+
+            response
+        }
+        Err(e) => Response::Error(ControlError::new(ControlErrorKind::Failed, e)),
+    }
+}
+
+async fn write_payload(stream: &mut Async<UnixStream>, payload: &[u8]) -> Result<()> {
+    stream
+        .write_all(&Header::new(payload.len()).to_bytes())
+        .await
+        .context("failed to write header")?;
+    stream
+        .write_all(payload)
+        .await
+        .context("failed to write payload")?;
+
+    Ok(())
+}
+
+async fn stream_events(
+    mut stream: Async<UnixStream>,
+    unit: Option<String>,
+    replay: bool,
+) -> Result<()> {
+    let events = monitor::subscribe(replay);
+
+    write_payload(&mut stream, &Response::Ok.encode()?).await?;
+
+    while let Ok(event) = events.recv().await {
+        // events about the whole system are always sent
+        if unit
+            .as_deref()
+            .is_some_and(|u| event.unit().is_some_and(|e| e != u))
+        {
+            continue;
+        }
+
+        // the subscriber went away
+        if write_payload(&mut stream, &event.encode()?).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_client(mut stream: Async<UnixStream>) -> Result<()> {
+    let uid = getsockopt(stream.get_ref(), sockopt::PeerCredentials)
+        .context("failed to get peer credentials")?
+        .uid();
+
+    // requests handled once the caller has been answered
+    let (response, deferred) = match read_request(&mut stream).await {
+        Ok(request) if uid != 0 && !request.is_read_only() => (
+            Response::Error(ControlError::new(
+                ControlErrorKind::Denied,
+                "operation not permitted",
+            )),
+            None,
+        ),
+        Ok(Request::Subscribe { unit, replay }) => {
+            return stream_events(stream, unit, replay).await
+        }
+        // teardown doesn't return, so the caller is answered first
+        Ok(request @ Request::Power(_)) => (Response::Ok, Some(request)),
+        Ok(request) if !request.is_blocking() => (Response::Ok, Some(request)),
+        Ok(request) => (handle_request(request).await, None),
+        Err(e) => (Response::Error(e), None),
+    };
+
+    write_payload(&mut stream, &response.encode()?).await?;
+
+    drop(stream);
+
+    match deferred {
+        Some(Request::Power(op)) => {
+            monitor::publish(EventKind::Power(op));
+
+            teardown(Some(match op {
+                PowerOp::Halt => RebootMode::RB_HALT_SYSTEM,
+                PowerOp::Poweroff => RebootMode::RB_POWER_OFF,
+                PowerOp::Reboot => RebootMode::RB_AUTOBOOT,
+                PowerOp::Kexec => RebootMode::RB_KEXEC,
+            }))?
+        }
+        Some(request) => {
+            if let Response::Error(e) = handle_request(request).await {
+                warn!("{}", e);
+            }
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+async fn listen_socket() -> Result<()> {
+    // left behind if init was re-executed
+    let _ = fs::remove_file(constants::KAN_SOCKET);
+
+    let listener = Async::<UnixListener>::bind(constants::KAN_SOCKET)
+        .context("failed to bind control socket")?;
+
+    // callers are authenticated by their credentials instead
+    fs::set_permissions(constants::KAN_SOCKET, fs::Permissions::from_mode(0o666))
+        .context("failed to set control socket permissions")?;
 
     loop {
-        let mut buff = vec![0u8; 512];
-
-        let mut file = match File::open(constants::KAN_PIPE).await {
-            Ok(f) => f,
-            Err(e) => match e.raw_os_error() {
-                None => return Err(e.into()),
-                Some(n) => {
-                    if Errno::from_raw(n) == Errno::EINTR {
-                        continue;
-                    } else {
-                        return Err(e.into());
-                    }
-                }
-            },
+        let (stream, _) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("failed to accept connection: {}", e);
+                continue;
+            }
         };
 
-        file.read(&mut buff).await.context("failed to read pipe")?;
-
-        spawn(pipe_handler(buff)).detach();
+        spawn(async move {
+            if let Err(e) = handle_client(stream).await {
+                warn!("{}", e);
+            }
+        })
+        .detach();
     }
 }
 
 async fn inner_ev_loop() -> Result<()> {
-    let handles = [spawn(listen_signal()), spawn(listen_file())];
+    let handles = [
+        spawn(listen_signal()),
+        spawn(listen_socket()),
+        // restarts not being reported isn't worth failing over
+        spawn(async {
+            if let Err(e) = watch_restarts().await {
+                warn!("{}", e);
+            }
+
+            Ok(())
+        }),
+    ];
 
     for handle in handles {
         handle.await?;
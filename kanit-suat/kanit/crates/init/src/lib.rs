@@ -25,6 +25,7 @@ use nix::unistd::{sync, Pid};
 
 #[cfg(feature = "baked-rc")]
 use baked_rc::*;
+use cmdline::KernelOptions;
 #[cfg(not(feature = "testing"))]
 use ev_loop::ev_loop;
 use kanit_common::constants;
@@ -43,20 +44,30 @@ use rc::*;
 mod baked_rc;
 #[cfg(not(feature = "testing"))]
 mod bsod;
+mod cmdline;
 #[cfg(not(feature = "testing"))]
 mod ev_loop;
+#[cfg(not(feature = "testing"))]
+mod monitor;
 #[cfg(not(feature = "baked-rc"))]
 mod rc;
 
 #[cfg(feature = "timings")]
 fn write_scope(file: &mut File, scope: &Scope) -> Result<()> {
-    let scope_fmt = format!(
-        "{} {} {}\n",
+    let mut scope_fmt = format!(
+        "{} {} {}",
         scope.name,
         scope.duration.unwrap_or(Duration::from_secs(0)).as_micros(),
         scope.level
     );
 
+    if let Some(ref note) = scope.note {
+        scope_fmt.push(' ');
+        scope_fmt.push_str(note);
+    }
+
+    scope_fmt.push('\n');
+
     file.write(scope_fmt.as_bytes())
         .context("failed to write scope")?;
     Ok(())
@@ -73,7 +84,7 @@ fn write_timing() -> Result<()> {
     Ok(())
 }
 
-fn initialize() -> Result<()> {
+fn initialize(opts: &KernelOptions) -> Result<()> {
     let id = kanit_timing::push_scope("initialize");
 
     #[cfg(not(feature = "testing"))]
@@ -108,7 +119,12 @@ fn initialize() -> Result<()> {
 
     env::set_var("PATH", constants::KAN_PATH);
 
-    initialize_rc()?;
+    // services inherit the environment through their supervisors
+    for (key, value) in opts.setenv.iter() {
+        env::set_var(key, value);
+    }
+
+    initialize_rc(opts)?;
 
     kanit_timing::pop_scope(id);
 
@@ -153,7 +169,7 @@ fn failure_handle() {
 fn failure_handle() {
     eprintln!("dropping into emergency shell");
 
-    Command::new("sh").exec();
+    let _ = Command::new("sh").exec();
 
     eprintln!("failed to drop into emergency shell; good luck o7");
 
@@ -163,6 +179,16 @@ fn failure_handle() {
     }
 }
 
+// unlike the emergency shell, init keeps running to allow switching levels from the shell
+#[cfg(not(feature = "testing"))]
+fn rescue_shell() {
+    eprintln!("dropping into rescue shell, continue booting with `kanit runlevel default`");
+
+    if let Err(e) = Command::new("sh").spawn() {
+        error!("failed to start rescue shell: {}", e);
+    }
+}
+
 pub fn handle_cli() -> ExitCode {
     if process::id() != 1 {
         eprintln!("init must be ran as PID 1");
@@ -184,18 +210,27 @@ pub fn handle_cli() -> ExitCode {
 
     kanit_diagnostics::tap::header();
 
+    let opts = KernelOptions::read();
+
     #[cfg(not(feature = "testing"))]
-    if let Err(e) = kanit_diagnostics::InitializationLogger::init(LevelFilter::Debug) {
+    if let Err(e) =
+        kanit_diagnostics::InitializationLogger::init(opts.log.unwrap_or(LevelFilter::Debug))
+    {
         eprintln!("{}", e);
     }
 
     kanit_timing::register();
 
-    if let Err(e) = initialize() {
+    if let Err(e) = initialize(&opts) {
         error!("failed to initialize: {}", e);
         failure_handle();
     }
 
+    #[cfg(not(feature = "testing"))]
+    if opts.rescue {
+        rescue_shell();
+    }
+
     #[cfg(not(feature = "testing"))] // no way to test yet
     if let Err(e) = ev_loop() {
         error!("event loop failed: {}", e);
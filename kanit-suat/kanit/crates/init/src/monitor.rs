@@ -0,0 +1,133 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_channel::{bounded, Receiver, Sender};
+use async_io::Async;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result};
+use kanit_control::{Event, EventKind};
+use kanit_supervisor::UnitState;
+
+/// Recent events kept for subscribers asking for a replay, such as those of the boot.
+const HISTORY: usize = 256;
+/// Events queued for a subscriber before it's dropped for being too slow.
+const BACKLOG: usize = 256;
+
+#[derive(Default)]
+struct Hub {
+    history: VecDeque<Event>,
+    subscribers: Vec<Sender<Event>>,
+}
+
+thread_local! {
+    static HUB: RefCell<Hub> = RefCell::default();
+}
+
+pub fn publish(kind: EventKind) {
+    let event = Event {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        kind,
+    };
+
+    HUB.with_borrow_mut(|hub| {
+        if hub.history.len() == HISTORY {
+            hub.history.pop_front();
+        }
+
+        hub.history.push_back(event.clone());
+
+        // closed or full channels belong to subscribers which went away
+        hub.subscribers
+            .retain(|s| s.try_send(event.clone()).is_ok());
+    });
+}
+
+pub fn subscribe(replay: bool) -> Receiver<Event> {
+    let (tx, rx) = bounded(HISTORY + BACKLOG);
+
+    HUB.with_borrow_mut(|hub| {
+        if replay {
+            for event in hub.history.iter() {
+                let _ = tx.try_send(event.clone());
+            }
+        }
+
+        hub.subscribers.push(tx);
+    });
+
+    rx
+}
+
+fn restarts(name: &str) -> Option<(u64, bool)> {
+    UnitState::read(name)
+        .ok()
+        .map(|state| (state.restarts, state.failed))
+}
+
+/// Publishes restarts from the state files supervisors replace whenever their process changes.
+pub async fn watch_restarts() -> Result<()> {
+    fs::create_dir_all(constants::KAN_UNIT_STATE_DIR)
+        .context("failed to create state directory")?;
+
+    let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+        .context("failed to initialize inotify")?;
+
+    // states are written to a temporary file then renamed over
+    inotify
+        .add_watch(constants::KAN_UNIT_STATE_DIR, AddWatchFlags::IN_MOVED_TO)
+        .context("failed to watch unit states")?;
+
+    let inotify = Async::new(inotify).context("failed to register inotify")?;
+
+    // restarts which happened before init started watching aren't reported
+    let mut known = fs::read_dir(constants::KAN_UNIT_STATE_DIR)
+        .context("failed to read state directory")?
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter_map(|file| {
+            let name = file.strip_suffix(".state")?;
+
+            Some((name.to_string(), restarts(name)?))
+        })
+        .collect::<HashMap<_, _>>();
+
+    loop {
+        let events = inotify
+            .read_with(|i| i.read_events().map_err(io::Error::from))
+            .await
+            .context("failed to read inotify events")?;
+
+        for event in events {
+            let Some(name) = event
+                .name
+                .as_ref()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".state"))
+            else {
+                continue;
+            };
+
+            let Some((count, failed)) = restarts(name) else {
+                continue;
+            };
+
+            let (last_count, last_failed) = known
+                .insert(name.to_string(), (count, failed))
+                .unwrap_or_default();
+
+            if count > last_count || (failed && !last_failed) {
+                publish(EventKind::Restarted {
+                    unit: name.to_string(),
+                    restarts: count,
+                    failed,
+                });
+            }
+        }
+    }
+}
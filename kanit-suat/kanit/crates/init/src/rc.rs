@@ -2,15 +2,30 @@ use std::path::Path;
 use std::process::Command;
 
 use kanit_common::error::{Context, ErrorKind, Result, StaticError};
+#[cfg(not(feature = "testing"))]
+use kanit_control::{Request, Response};
+#[cfg(not(feature = "testing"))]
+use kanit_executor::process;
+
+use crate::cmdline::KernelOptions;
 
-pub fn initialize_rc() -> Result<()> {
+// boot overrides are passed to the start script through its environment
+pub fn initialize_rc(opts: &KernelOptions) -> Result<()> {
     if !Path::new("/etc/rc.start").exists() {
         Err(StaticError("failed to find a start script")).kind(ErrorKind::Unrecoverable)?;
     }
 
-    Command::new("/etc/rc.start")
-        .spawn()
-        .context("failed to start rc.start")?;
+    let mut cmd = Command::new("/etc/rc.start");
+
+    if let Some(level) = opts.level {
+        cmd.env("KANIT_LEVEL", level.to_string());
+    }
+
+    if !opts.mask.is_empty() {
+        cmd.env("KANIT_MASK", opts.mask.join(","));
+    }
+
+    cmd.spawn().context("failed to start rc.start")?;
 
     Ok(())
 }
@@ -27,16 +42,40 @@ pub fn teardown_rc() -> Result<()> {
     Ok(())
 }
 
+// the scripts wait on their own children, but orphans and the scripts themselves are left to init
+#[cfg(not(feature = "testing"))]
+pub fn child_rc() -> Result<()> {
+    process::reap();
+
+    Ok(())
+}
+
+// the event script receives requests as `start:<unit>:<level>`, `runlevel:<level>`, and so on
 #[cfg(not(feature = "testing"))]
-pub async fn event_rc(ev: Vec<u8>) -> Result<()> {
+pub async fn event_rc(request: Request) -> Result<Response> {
     if !Path::new("/etc/rc.event").exists() {
-        return Ok(());
+        Err(StaticError("failed to find an event script"))?;
     }
 
+    let ev = match request {
+        Request::Start { unit, level, .. } => format!("start:{}:{}", unit, level),
+        Request::Stop { unit, level, .. } => format!("stop:{}:{}", unit, level),
+        Request::Restart { unit, level, .. } => format!("restart:{}:{}", unit, level),
+        Request::Reload => "db-reload".to_string(),
+        Request::Runlevel { level } => format!(
+            "runlevel:{}",
+            level.map_or("shutdown".to_string(), |l| l.to_string())
+        ),
+        Request::Status { .. } | Request::Power(_) | Request::Subscribe { .. } => {
+            Err(StaticError("unsupported by the event script"))?
+        }
+    };
+
+    // not waited on as it would block the event loop
     Command::new("/etc/rc.event")
-        .arg(String::from_utf8_lossy(&ev).to_string())
+        .arg(ev)
         .spawn()
         .context_kind("failed to start rc.event", ErrorKind::Recoverable)?;
 
-    Ok(())
+    Ok(Response::Ok)
 }
@@ -1,20 +1,26 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 #[cfg(not(feature = "testing"))]
 use std::io::{stdin, stdout, Write};
+use std::rc::Rc;
+use std::thread;
 
-use async_process::driver;
 use log::{debug, error, info, warn};
 
 use kanit_common::constants;
 use kanit_common::error::{Context, Error, Result};
 use kanit_diagnostics::tap as kanit_tap;
 use kanit_diagnostics::timing as kanit_timing;
-use kanit_executor::{join_all, spawn};
-use kanit_unit::{RcUnit, UnitName};
+use kanit_executor::process::driver;
+use kanit_executor::{join_all, spawn, try_join_dag};
+use kanit_unit::{Preparation, RcUnit, Unit, UnitName};
 
-pub use crate::event::event;
+use crate::event::EV_LOCK;
+pub use crate::event::{event, reap};
 use crate::loader;
-use crate::loader::Loader;
+pub use crate::loader::observe;
+use crate::loader::{Loader, StateTable};
 
 #[cfg(not(feature = "testing"))]
 fn critical_unit_fail(err: Error) -> Result<()> {
@@ -47,8 +53,62 @@ fn write_db(loader: &Loader) -> Result<()> {
     fs::write(constants::KAN_DB, loader.dump_db()?).context("failed to write database")
 }
 
-async fn start_unit(tuple: (usize, RcUnit)) -> Result<Option<UnitName>> {
-    let (j, unit) = tuple;
+/// Overrides for a single boot, such as those given on the kernel command line.
+#[derive(Debug, Clone, Default)]
+pub struct BootOptions {
+    /// The last level to start, all levels are started if unset.
+    pub level: Option<usize>,
+    /// Units which are skipped during this boot.
+    pub mask: HashSet<UnitName>,
+}
+
+// shared between the units of a level while they start
+#[derive(Default)]
+struct LevelState {
+    mask: HashSet<UnitName>,
+    states: Rc<StateTable>,
+    // units finish in any order so tests are numbered as they complete
+    tests: Cell<usize>,
+    // units which didn't start, and whether they were skipped rather than failed
+    // shared by every level as units may need units of earlier levels
+    failed: Rc<RefCell<HashMap<UnitName, bool>>>,
+}
+
+impl LevelState {
+    fn next_test(&self) -> usize {
+        self.tests.set(self.tests.get() + 1);
+        self.tests.get()
+    }
+
+    // dependents may refer to the unit through the names it provides
+    fn fail(&self, unit: &dyn Unit, skipped: bool) {
+        let mut failed = self.failed.borrow_mut();
+
+        failed.insert(unit.name(), skipped);
+
+        for name in unit.dependencies().provides {
+            failed.insert(name, skipped);
+        }
+    }
+
+    // units which only `want` a failed unit still start
+    fn skip_reason(&self, needs: &[UnitName]) -> Option<String> {
+        let failed = self.failed.borrow();
+
+        needs.iter().find_map(|n| {
+            failed.get(n).map(|skipped| {
+                if *skipped {
+                    format!("needs `{}` which was skipped", n)
+                } else {
+                    format!("needs `{}` which failed to start", n)
+                }
+            })
+        })
+    }
+}
+
+async fn start_unit(tuple: (Rc<LevelState>, RcUnit)) -> Result<Option<UnitName>> {
+    let (state, unit) = tuple;
 
     let mut unit_b = unit.borrow_mut();
 
@@ -56,16 +116,47 @@ async fn start_unit(tuple: (usize, RcUnit)) -> Result<Option<UnitName>> {
 
     let id = kanit_timing::push_scope(format!("unit:{}", unit_b.name()));
 
-    if !unit_b.prepare().await? {
-        warn!("failed preparations for {}", unit_b.name());
-        kanit_tap::not_ok(j + 1, Some("failed preparations"));
+    state.states.activating(unit_b.name());
+
+    if state.mask.contains(&unit_b.name()) {
+        let reason = "masked on the kernel command line";
+
+        info!("skipping {}, {}", unit_b.name(), reason);
+        state.states.skipped(unit_b.name(), reason);
+        kanit_tap::skip(state.next_test(), Some(unit_b.name()), reason);
+        kanit_timing::annotate(id, format!("skipped, {}", reason));
+        kanit_timing::pop_scope(id);
+        state.fail(&*unit_b, true);
+        return Ok(None);
+    }
+
+    if let Some(reason) = state.skip_reason(&unit_b.dependencies().needs) {
+        warn!("skipping {}, {}", unit_b.name(), reason);
+        state.states.skipped(unit_b.name(), &reason);
+        kanit_tap::skip(state.next_test(), Some(unit_b.name()), &reason);
+        kanit_timing::annotate(id, format!("skipped, {}", reason));
+        kanit_timing::pop_scope(id);
+        state.fail(&*unit_b, true);
+        return Ok(None);
+    }
+
+    if let Preparation::Skip(reason) = unit_b.prepare().await? {
+        info!("skipping {}, {}", unit_b.name(), reason);
+        state.states.skipped(unit_b.name(), &reason);
+        kanit_tap::skip(state.next_test(), Some(unit_b.name()), &reason);
+        kanit_timing::annotate(id, format!("skipped, {}", reason));
+        kanit_timing::pop_scope(id);
+        state.fail(&*unit_b, true);
         return Ok(None);
     }
 
     if let Err(e) = unit_b.start().await {
+        kanit_timing::annotate(id, format!("failed, {}", e));
+        state.states.failed(unit_b.name(), &e);
+
         if e.is_recoverable() {
             warn!("{}", e);
-            kanit_tap::not_ok(j + 1, Some(e));
+            kanit_tap::not_ok(state.next_test(), Some(e));
         } else {
             error!("{}", e);
             critical_unit_fail(e)?;
@@ -73,19 +164,44 @@ async fn start_unit(tuple: (usize, RcUnit)) -> Result<Option<UnitName>> {
 
         kanit_timing::pop_scope(id);
 
+        state.fail(&*unit_b, false);
+
         return Ok(None);
     }
 
     kanit_timing::pop_scope(id);
 
-    kanit_tap::ok(j + 1, Some(unit_b.name()));
+    state.states.active(&*unit_b);
+
+    kanit_tap::ok(state.next_test(), Some(unit_b.name()));
 
     debug!("finished loading unit {}", unit_b.name());
 
     Ok(Some(unit_b.name().clone()))
 }
 
-pub async fn start() -> Result<()> {
+async fn start_level(
+    state: Rc<LevelState>,
+    units: Vec<RcUnit>,
+    waits: &[Vec<usize>],
+) -> Result<Vec<Option<UnitName>>> {
+    // bind the level's sockets up front so its units can connect to each other regardless of
+    // start order, earlier levels mount `/run` and the filesystems the sockets may live on
+    for unit in &units {
+        if let Err(e) = unit.borrow_mut().listen() {
+            warn!("{}", e);
+        }
+    }
+
+    // each unit starts as soon as the units it waits on have started
+    try_join_dag(
+        units.into_iter().map(|u| start_unit((state.clone(), u))),
+        waits,
+    )
+    .await
+}
+
+pub async fn start(opts: BootOptions) -> Result<()> {
     kanit_timing::register();
 
     loader::init_loader()?;
@@ -94,11 +210,18 @@ pub async fn start() -> Result<()> {
 
     let loader_levels = loader.get_levels();
 
+    // later levels can still be reached with `kanit runlevel`
+    let boot_levels = opts
+        .level
+        .map_or(loader_levels, |l| loader_levels.min(l + 1));
+
     kanit_tap::plan(loader_levels * 2); // include teardown as well
 
+    let failed = Rc::new(RefCell::new(HashMap::new()));
+
     let driver_task = spawn(driver());
 
-    for i in 0..loader_levels {
+    for i in 0..boot_levels {
         info!("starting level {}", i);
 
         let scope_str = format!("level:{}", i);
@@ -107,26 +230,21 @@ pub async fn start() -> Result<()> {
 
         let id = kanit_timing::push_scope(&scope_str);
 
-        let level = loader.get_level(i);
-
-        kanit_tap::plan(level.len());
-
-        for (j, group) in level.into_iter().enumerate() {
-            let group_str = format!("group:{}", j);
+        let (units, waits) = loader.get_graph(i);
 
-            kanit_tap::enter_subtest(Some(&group_str));
-            kanit_tap::plan(group.len());
+        kanit_tap::plan(units.len());
 
-            let handles = join_all(group.into_iter().enumerate().map(start_unit)).await;
+        let state = Rc::new(LevelState {
+            mask: opts.mask.clone(),
+            states: loader.states.clone(),
+            failed: failed.clone(),
+            ..Default::default()
+        });
 
-            for handle in handles {
-                if let Some(name) = handle? {
-                    loader.mark_started(i, name);
-                }
-            }
+        let started = start_level(state, units, &waits).await?;
 
-            kanit_tap::exit_subtest();
-            kanit_tap::ok(j + 1, Some(&group_str));
+        for name in started.into_iter().flatten() {
+            loader.mark_started(i, name);
         }
 
         kanit_timing::pop_scope(id);
@@ -135,6 +253,15 @@ pub async fn start() -> Result<()> {
         kanit_tap::ok(i + 1, Some(&scope_str));
     }
 
+    loader.level = boot_levels.checked_sub(1);
+
+    // a boot limited to fewer levels, such as a rescue boot, doesn't end the first boot
+    if boot_levels == loader_levels && fs::metadata(constants::KAN_BOOTED).is_err() {
+        if let Err(e) = fs::write(constants::KAN_BOOTED, "").context("failed to mark boot") {
+            warn!("{}", e);
+        }
+    }
+
     if loader.defaulted {
         if let Err(e) = write_db(&loader) {
             warn!("{}", e);
@@ -146,16 +273,22 @@ pub async fn start() -> Result<()> {
     Ok(())
 }
 
-async fn stop_unit(tuple: (usize, RcUnit)) -> Result<()> {
-    let (j, unit) = tuple;
+async fn stop_unit(tuple: (Rc<StateTable>, usize, RcUnit)) -> Result<()> {
+    let (states, j, unit) = tuple;
 
     let mut unit_b = unit.borrow_mut();
 
     debug!("unloading unit {}", unit_b.name());
 
+    states.deactivating(unit_b.name());
+
     match unit_b.stop().await {
-        Ok(_) => kanit_tap::ok(j + 1, Some(unit_b.name())),
+        Ok(_) => {
+            states.inactive(unit_b.name());
+            kanit_tap::ok(j + 1, Some(unit_b.name()))
+        }
         Err(e) => {
+            states.failed(unit_b.name(), &e);
             kanit_tap::not_ok(j + 1, Some(unit_b.name()));
 
             if e.is_recoverable() {
@@ -172,10 +305,23 @@ async fn stop_unit(tuple: (usize, RcUnit)) -> Result<()> {
 }
 
 pub async fn teardown() -> Result<()> {
-    let loader = Loader::obtain()?.borrow();
+    // requests keep the loader borrowed while units are waited on, so those in flight finish first
+    // and later ones wait, unless this is after a panic which may have happened in a request
+    let _lock = if thread::panicking() {
+        EV_LOCK.try_lock()
+    } else {
+        Some(EV_LOCK.lock().await)
+    };
+
+    let loader = Loader::obtain()?
+        .try_borrow()
+        .context("failed to obtain loader")?;
 
     let loader_levels = loader.get_levels();
 
+    // commands run by units while stopping are waited on
+    let driver_task = spawn(driver());
+
     for i in (0..loader_levels).rev() {
         info!("stopping level {}", i);
 
@@ -194,7 +340,13 @@ pub async fn teardown() -> Result<()> {
             kanit_tap::enter_subtest(Some(&group_str));
             kanit_tap::plan(group.len());
 
-            let handles = join_all(group.into_iter().enumerate().map(stop_unit)).await;
+            let handles = join_all(
+                group
+                    .into_iter()
+                    .enumerate()
+                    .map(|(j, u)| stop_unit((loader.states.clone(), j, u))),
+            )
+            .await;
 
             for handle in handles {
                 handle?;
@@ -209,5 +361,141 @@ pub async fn teardown() -> Result<()> {
         kanit_tap::ok(loader_levels + (loader_levels - i), Some(&scope_str));
     }
 
+    driver_task.cancel().await;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+
+    use async_trait::async_trait;
+
+    use kanit_common::error::{ErrorKind, StaticError};
+    use kanit_unit::{socket, wrap_unit, Dependencies};
+
+    use super::*;
+
+    // stands in for the `run` unit mounting a tmpfs over `/run`
+    struct MountRun(PathBuf);
+
+    #[async_trait]
+    impl Unit for MountRun {
+        fn name(&self) -> UnitName {
+            UnitName::from("run")
+        }
+
+        async fn start(&mut self) -> Result<()> {
+            let _ = fs::remove_dir_all(&self.0);
+            fs::create_dir_all(&self.0).context("failed to create run")
+        }
+    }
+
+    struct Listening(String, Vec<OwnedFd>);
+
+    #[async_trait]
+    impl Unit for Listening {
+        fn name(&self) -> UnitName {
+            UnitName::from("listening")
+        }
+
+        fn listen(&mut self) -> Result<()> {
+            self.1.push(socket::bind(&self.0)?);
+            Ok(())
+        }
+
+        async fn start(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl Unit for Failing {
+        fn name(&self) -> UnitName {
+            UnitName::from("failing")
+        }
+
+        async fn start(&mut self) -> Result<()> {
+            Err(StaticError("failed")).kind(ErrorKind::Recoverable)
+        }
+    }
+
+    struct Needing;
+
+    #[async_trait]
+    impl Unit for Needing {
+        fn name(&self) -> UnitName {
+            UnitName::from("needing")
+        }
+
+        fn dependencies(&self) -> Dependencies {
+            Dependencies::new().need(UnitName::from("failing")).clone()
+        }
+
+        async fn start(&mut self) -> Result<()> {
+            panic!("started a unit which needs a failed unit");
+        }
+    }
+
+    fn needs_failed_in_earlier_level() {
+        let failed = Rc::new(RefCell::new(HashMap::new()));
+
+        let started = kanit_executor::block(async move {
+            let mut started = vec![];
+
+            for unit in [wrap_unit(Failing), wrap_unit(Needing)] {
+                let state = Rc::new(LevelState {
+                    failed: failed.clone(),
+                    ..Default::default()
+                });
+
+                started.extend(start_level(state, vec![unit], &[vec![]]).await.unwrap());
+            }
+
+            started
+        });
+
+        assert_eq!(started, [None, None]);
+    }
+
+    fn unix_socket_under_run() {
+        let run = std::env::temp_dir().join(format!("kanit-run-{}", std::process::id()));
+        let sock = run.join("foo.sock");
+
+        // a stale socket from a previous boot
+        fs::create_dir_all(&run).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&sock).unwrap());
+
+        let levels = vec![
+            wrap_unit(MountRun(run.clone())),
+            wrap_unit(Listening(format!("unix:{}", sock.display()), vec![])),
+        ];
+
+        // the units own the bound sockets
+        let units = levels.clone();
+
+        kanit_executor::block(async {
+            for unit in units {
+                start_level(Rc::new(LevelState::default()), vec![unit], &[vec![]])
+                    .await
+                    .unwrap();
+            }
+        });
+
+        assert!(UnixStream::connect(&sock).is_ok());
+
+        let _ = fs::remove_dir_all(&run);
+    }
+
+    // the executor is bound to the first thread using it, so every case runs within one test
+    #[test]
+    fn start_levels() {
+        needs_failed_in_earlier_level();
+        unix_socket_under_run();
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::Path;
+use std::{env, fs};
+
+#[cfg(feature = "rkyv")]
+use rkyv::Archive;
+
+use kanit_common::constants;
+
+/// Conditions checked before starting a unit, a leading `!` inverts a condition.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct Conditions {
+    /// Paths which have to exist.
+    pub condition_path_exists: Vec<String>,
+    /// Options which have to be on the kernel command line, either `opt` or `opt=value`.
+    pub condition_kernel_cmdline: Vec<String>,
+    /// Either `yes`, `no`, `vm`, `container`, or a specific technology such as `kvm`.
+    pub condition_virtualization: Option<String>,
+    /// Whether the system has to be booting for the first time.
+    pub condition_first_boot: Option<bool>,
+    /// The architecture the system has to run on, such as `x86_64`.
+    pub condition_arch: Option<String>,
+}
+
+/// What conditions are checked against, read from the running system with [`Host::detect`].
+#[derive(Debug, Clone, Default)]
+pub struct Host {
+    /// The kernel command line.
+    pub cmdline: String,
+    /// Whether no boot has completed on the system yet.
+    pub first_boot: bool,
+    /// The kind of virtualization (`vm` or `container`) and the technology used.
+    pub virtualization: Option<(&'static str, &'static str)>,
+}
+
+impl Host {
+    pub fn detect() -> Self {
+        Self {
+            cmdline: fs::read_to_string("/proc/cmdline").unwrap_or_default(),
+            // written by kanit once every level has started
+            first_boot: !Path::new(constants::KAN_BOOTED).exists(),
+            virtualization: virtualization(),
+        }
+    }
+}
+
+fn negated(condition: &str) -> (bool, &str) {
+    condition
+        .strip_prefix('!')
+        .map_or((false, condition), |c| (true, c))
+}
+
+fn virtualization() -> Option<(&'static str, &'static str)> {
+    if Path::new("/.dockerenv").exists() {
+        return Some(("container", "docker"));
+    }
+
+    if Path::new("/run/.containerenv").exists() {
+        return Some(("container", "podman"));
+    }
+
+    // container managers tell init through its environment
+    if let Ok(container) = env::var("container") {
+        return Some((
+            "container",
+            match container.as_str() {
+                "lxc" => "lxc",
+                "systemd-nspawn" => "systemd-nspawn",
+                _ => "container",
+            },
+        ));
+    }
+
+    let vendor = fs::read_to_string("/sys/class/dmi/id/sys_vendor").unwrap_or_default();
+    let product = fs::read_to_string("/sys/class/dmi/id/product_name").unwrap_or_default();
+
+    if product.starts_with("KVM") {
+        return Some(("vm", "kvm"));
+    }
+
+    for (pattern, name) in [
+        ("QEMU", "qemu"),
+        ("VMware", "vmware"),
+        ("innotek", "oracle"),
+        ("Microsoft", "microsoft"),
+        ("Xen", "xen"),
+        ("Amazon EC2", "amazon"),
+    ] {
+        if vendor.contains(pattern) {
+            return Some(("vm", name));
+        }
+    }
+
+    let hypervisor = fs::read_to_string("/proc/cpuinfo").is_ok_and(|c| {
+        c.lines()
+            .filter(|l| l.starts_with("flags"))
+            .any(|l| l.split_whitespace().any(|f| f == "hypervisor"))
+    });
+
+    hypervisor.then_some(("vm", "vm"))
+}
+
+impl Conditions {
+    /// The first condition which isn't met on `host`, if any.
+    pub fn unmet(&self, host: &Host) -> Option<String> {
+        for path in self.condition_path_exists.iter() {
+            let (negate, p) = negated(path);
+
+            if Path::new(p).exists() == negate {
+                return Some(format!("condition-path-exists `{}` not met", path));
+            }
+        }
+
+        for opt in self.condition_kernel_cmdline.iter() {
+            let (negate, o) = negated(opt);
+
+            // `opt` matches any value given to it while `opt=value` has to match exactly
+            let set = host.cmdline.split_whitespace().any(|w| {
+                w == o || (!o.contains('=') && w.split_once('=').is_some_and(|(k, _)| k == o))
+            });
+
+            if set == negate {
+                return Some(format!("condition-kernel-cmdline `{}` not met", opt));
+            }
+        }
+
+        if let Some(ref virt) = self.condition_virtualization {
+            let (negate, v) = negated(virt);
+            let detected = host.virtualization;
+
+            let matches = match v {
+                "yes" | "true" => detected.is_some(),
+                "no" | "false" => detected.is_none(),
+                _ => detected.is_some_and(|(kind, name)| kind == v || name == v),
+            };
+
+            if matches == negate {
+                return Some(format!("condition-virtualization `{}` not met", virt));
+            }
+        }
+
+        if let Some(first_boot) = self.condition_first_boot {
+            if host.first_boot != first_boot {
+                return Some(format!("condition-first-boot `{}` not met", first_boot));
+            }
+        }
+
+        if let Some(ref arch) = self.condition_arch {
+            let (negate, a) = negated(arch);
+
+            if (env::consts::ARCH == a) == negate {
+                return Some(format!("condition-arch `{}` not met", arch));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(cmdline: &str) -> Host {
+        Host {
+            cmdline: cmdline.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn cmdline(conditions: &[&str]) -> Conditions {
+        Conditions {
+            condition_kernel_cmdline: conditions.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn kernel_cmdline() {
+        let host = host("root=/dev/sda1 quiet console=ttyS0");
+
+        assert!(cmdline(&["quiet"]).unmet(&host).is_none());
+        assert!(cmdline(&["console"]).unmet(&host).is_none());
+        assert!(cmdline(&["console=ttyS0"]).unmet(&host).is_none());
+        assert!(cmdline(&["!splash"]).unmet(&host).is_none());
+
+        assert!(cmdline(&["console=tty0"]).unmet(&host).is_some());
+        assert!(cmdline(&["!quiet"]).unmet(&host).is_some());
+        assert!(cmdline(&["quiet", "splash"]).unmet(&host).is_some());
+    }
+
+    #[test]
+    fn first_boot() {
+        let conditions = Conditions {
+            condition_first_boot: Some(true),
+            ..Default::default()
+        };
+
+        let mut host = host("");
+
+        host.first_boot = true;
+        assert!(conditions.unmet(&host).is_none());
+
+        host.first_boot = false;
+        assert!(conditions.unmet(&host).is_some());
+    }
+
+    #[test]
+    fn virtualization_kind() {
+        let conditions = |v: &str| Conditions {
+            condition_virtualization: Some(v.to_string()),
+            ..Default::default()
+        };
+
+        let mut host = host("");
+
+        assert!(conditions("no").unmet(&host).is_none());
+        assert!(conditions("vm").unmet(&host).is_some());
+
+        host.virtualization = Some(("vm", "kvm"));
+
+        assert!(conditions("yes").unmet(&host).is_none());
+        assert!(conditions("vm").unmet(&host).is_none());
+        assert!(conditions("kvm").unmet(&host).is_none());
+        assert!(conditions("!container").unmet(&host).is_none());
+        assert!(conditions("qemu").unmet(&host).is_some());
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use send_wrapper::SendWrapper;
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result, WithError};
+use kanit_unit::{wrap_unit, Dependencies, Preparation, RcUnit, Unit, UnitName, UnitNameExt};
+
+use crate::db::{Conditions, DbUnit, Host};
+
+const DEPENDENCY_KEYS: [&str; 7] = [
+    "needs",
+    "uses",
+    "wants",
+    "before",
+    "after",
+    "conflicts",
+    "provides",
+];
+
+/// A file merged over a unit.
+pub struct Fragment {
+    pub source: String,
+    pub table: Table,
+}
+
+/// A unit with its drop-ins merged, along with the files each key came from.
+pub struct Merged {
+    pub table: Table,
+    pub origins: BTreeMap<String, Vec<String>>,
+}
+
+impl Merged {
+    pub fn new(base: Table, source: &str) -> Self {
+        let origins = base
+            .keys()
+            .map(|k| (k.clone(), vec![source.to_string()]))
+            .collect();
+
+        Self {
+            table: base,
+            origins,
+        }
+    }
+
+    /// Lists are appended to, or reset when empty. Any other value is replaced.
+    pub fn merge(&mut self, fragment: Fragment) {
+        for (key, value) in fragment.table {
+            let origins = self.origins.entry(key.clone()).or_default();
+
+            match (self.table.get_mut(&key), value) {
+                (Some(Value::Array(list)), Value::Array(more)) if !more.is_empty() => {
+                    list.extend(more);
+                    origins.push(fragment.source.clone());
+                }
+                (_, value) => {
+                    self.table.insert(key, value);
+                    *origins = vec![fragment.source.clone()];
+                }
+            }
+        }
+    }
+}
+
+/// Drop-ins of a unit, from `/etc/kanit/<unit>.d/*.toml` and, for instances, the template's directory.
+/// Fragments are sorted by file name, with instance fragments after template ones on ties.
+pub fn fragments(name: &UnitName) -> Result<Vec<Fragment>> {
+    let mut paths = vec![];
+
+    for dir in name.template().iter().chain([name]) {
+        let dir = PathBuf::from(constants::KAN_UNIT_DIR).join(format!("{}.d", dir));
+
+        if let Ok(entries) = fs::read_dir(&dir) {
+            paths.extend(
+                entries
+                    .filter_map(|e| e.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|e| e == "toml")),
+            );
+        }
+    }
+
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let source = path.to_string_lossy().to_string();
+            let contents = fs::read_to_string(&path).context("failed to read drop-in")?;
+            let table = contents.parse::<Table>().map_err(|e| {
+                let source = source.clone();
+                let e = e.to_string();
+
+                WithError::with(move || format!("failed to parse `{}`: {}", source, e))
+            })?;
+
+            Ok(Fragment { source, table })
+        })
+        .collect()
+}
+
+/// The keys a drop-in can set on a built-in unit.
+pub fn baked_table(unit: &dyn Unit) -> Table {
+    let deps = unit.dependencies();
+    let mut table = Table::new();
+
+    if let Some(description) = unit.description() {
+        table.insert("description".into(), description.into());
+    }
+
+    for (key, names) in DEPENDENCY_KEYS.into_iter().zip([
+        deps.needs,
+        deps.uses,
+        deps.wants,
+        deps.before,
+        deps.after,
+        deps.conflicts,
+        deps.provides,
+    ]) {
+        if !names.is_empty() {
+            table.insert(
+                key.into(),
+                names
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .into(),
+            );
+        }
+    }
+
+    table
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Patch {
+    description: Option<String>,
+    #[serde(default)]
+    needs: Vec<UnitName>,
+    #[serde(default)]
+    uses: Vec<UnitName>,
+    #[serde(default)]
+    wants: Vec<UnitName>,
+    #[serde(default)]
+    before: Vec<UnitName>,
+    #[serde(default)]
+    after: Vec<UnitName>,
+    #[serde(default)]
+    conflicts: Vec<UnitName>,
+    #[serde(default)]
+    provides: Vec<UnitName>,
+    #[serde(flatten)]
+    conditions: Conditions,
+}
+
+/// A built-in unit with its drop-ins applied.
+struct Patched {
+    unit: RcUnit,
+    description: Option<String>,
+    dependencies: Dependencies,
+    conditions: Conditions,
+}
+
+#[async_trait]
+impl Unit for Patched {
+    fn name(&self) -> UnitName {
+        self.unit.borrow().name()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        self.dependencies.clone()
+    }
+
+    fn pid(&self) -> Option<u32> {
+        self.unit.borrow().pid()
+    }
+
+    fn listen(&mut self) -> Result<()> {
+        self.unit.borrow_mut().listen()
+    }
+
+    // units are only ever driven from the executor's thread
+    async fn start(&mut self) -> Result<()> {
+        let unit = self.unit.clone();
+
+        SendWrapper::new(async move { unit.borrow_mut().start().await }).await
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        let unit = self.unit.clone();
+
+        SendWrapper::new(async move { unit.borrow_mut().stop().await }).await
+    }
+
+    async fn prepare(&self) -> Result<Preparation> {
+        if let Some(reason) = self.conditions.unmet(&Host::detect()) {
+            return Ok(Preparation::Skip(reason));
+        }
+
+        let unit = self.unit.clone();
+
+        SendWrapper::new(async move { unit.borrow().prepare().await }).await
+    }
+
+    async fn teardown(&self) -> Result<()> {
+        let unit = self.unit.clone();
+
+        SendWrapper::new(async move { unit.borrow().teardown().await }).await
+    }
+}
+
+/// Applies the drop-ins of a built-in unit, returning `None` if it has none.
+pub fn patch(unit: &RcUnit) -> Result<Option<RcUnit>> {
+    let name = unit.borrow().name();
+    let fragments = fragments(&name)?;
+
+    if fragments.is_empty() {
+        return Ok(None);
+    }
+
+    let mut merged = Merged::new(baked_table(&*unit.borrow()), "built-in");
+
+    fragments.into_iter().for_each(|f| merged.merge(f));
+
+    // anything else is part of the built-in unit's code
+    if let Some(key) = merged.table.keys().find(|k| {
+        *k != "description"
+            && !DEPENDENCY_KEYS.contains(&k.as_str())
+            && !k.starts_with("condition-")
+    }) {
+        let key = key.clone();
+
+        Err(WithError::with(move || {
+            format!("`{}` can't be set on built-in unit `{}`", key, name)
+        }))?;
+    }
+
+    let patch: Patch = Value::Table(merged.table)
+        .try_into()
+        .context("failed to parse drop-in")?;
+
+    let mut dependencies = Dependencies::new();
+
+    dependencies.needs = patch.needs;
+    dependencies.uses = patch.uses;
+    dependencies.wants = patch.wants;
+    dependencies.before = patch.before;
+    dependencies.after = patch.after;
+    dependencies.conflicts = patch.conflicts;
+    dependencies.provides = patch.provides;
+
+    Ok(Some(wrap_unit(Patched {
+        unit: unit.clone(),
+        description: patch.description,
+        dependencies,
+        conditions: patch.conditions,
+    })))
+}
+
+/// Applies the drop-ins of a unit from the database, returning `None` if it has none.
+/// Units are stored as they were enabled, so drop-ins are applied whenever they're loaded.
+pub fn apply(unit: &DbUnit) -> Result<Option<DbUnit>> {
+    let fragments = fragments(&unit.name)?;
+
+    if fragments.is_empty() {
+        return Ok(None);
+    }
+
+    let base = match Value::try_from(unit).context("failed to serialize unit")? {
+        Value::Table(table) => table,
+        _ => unreachable!("units serialize to tables"),
+    };
+
+    let mut merged = Merged::new(base, unit.source.as_deref().unwrap_or("database"));
+
+    fragments.into_iter().for_each(|f| merged.merge(f));
+
+    let name = unit.name.clone();
+
+    let mut patched: DbUnit = Value::Table(merged.table)
+        .try_into()
+        .with_context(move || format!("failed to apply drop-ins of `{}`", name))?;
+
+    // drop-ins of instances can refer to the instance like their template
+    if patched.name.instance().is_some() {
+        patched.instantiate(unit.name.clone())?;
+    }
+
+    Ok(Some(patched))
+}
+
+#[cfg(test)]
+mod tests {
+    use kanit_supervisor::Supervisor;
+
+    use super::*;
+    use crate::db::UnitKind;
+
+    #[test]
+    fn unit_survives_table() {
+        let mut unit = DbUnit::new(
+            UnitName::from("daemon"),
+            UnitKind::Daemon,
+            Some(UnitName::from("a daemon")),
+            Dependencies::new().need(UnitName::from("syslog")).clone(),
+            Supervisor {
+                cmd: "/bin/daemon".to_string(),
+                args: vec!["-f".to_string()],
+                restart_delay: Some(2),
+                ..Default::default()
+            },
+        );
+
+        unit.source = Some("/etc/kanit/daemon.toml".to_string());
+
+        let table = Value::try_from(&unit).unwrap();
+        let parsed: DbUnit = table.try_into().unwrap();
+
+        assert_eq!(parsed.name, unit.name);
+        assert_eq!(parsed.kind, UnitKind::Daemon);
+        assert_eq!(parsed.source, unit.source);
+        assert_eq!(parsed.needs, unit.needs);
+        assert_eq!(parsed.supervisor_opts.cmd, "/bin/daemon");
+        assert_eq!(parsed.supervisor_opts.args, ["-f"]);
+        assert_eq!(parsed.supervisor_opts.restart_delay, Some(2));
+    }
+}
@@ -0,0 +1,175 @@
+// the layout of databases written before they were versioned
+// these types must not change, new layouts get a new version instead
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+#[cfg(feature = "rkyv")]
+use rkyv::Archive;
+#[cfg(feature = "postcard")]
+use serde::{Deserialize, Serialize};
+
+use kanit_supervisor::{RestartPolicy, Supervisor};
+use kanit_unit::{Dependencies, UnitInfo, UnitName};
+
+use crate::db::{DbUnit, Level, UnitKind};
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "postcard", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "postcard", serde(rename_all = "lowercase"))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub enum UnitKindV0 {
+    Oneshot,
+    Daemon,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "postcard", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "postcard", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "postcard", serde(default))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct SupervisorV0 {
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub restart_delay: Option<u64>,
+    pub restart_attempts: Option<u64>,
+    pub restart_policy: Option<RestartPolicy>,
+    pub pwd: Option<String>,
+    pub root: Option<String>,
+    pub env: Vec<String>,
+    pub group: Option<String>,
+    pub user: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "postcard", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct DbUnitV0 {
+    pub name: UnitName,
+    pub kind: UnitKindV0,
+    pub description: Option<UnitName>,
+    #[cfg_attr(feature = "postcard", serde(default))]
+    pub before: Vec<UnitName>,
+    #[cfg_attr(feature = "postcard", serde(default))]
+    pub after: Vec<UnitName>,
+    #[cfg_attr(feature = "postcard", serde(default))]
+    pub needs: Vec<UnitName>,
+    #[cfg_attr(feature = "postcard", serde(default))]
+    pub uses: Vec<UnitName>,
+    #[cfg_attr(feature = "postcard", serde(default))]
+    pub wants: Vec<UnitName>,
+    #[cfg_attr(feature = "postcard", serde(flatten))]
+    pub supervisor_opts: SupervisorV0,
+    #[cfg_attr(feature = "postcard", serde(skip))]
+    pub pid: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "postcard", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "postcard", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct DependenciesV0 {
+    pub needs: Vec<UnitName>,
+    pub uses: Vec<UnitName>,
+    pub wants: Vec<UnitName>,
+    pub before: Vec<UnitName>,
+    pub after: Vec<UnitName>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "postcard", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "postcard", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct UnitInfoV0 {
+    pub name: UnitName,
+    pub dependencies: Rc<DependenciesV0>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "postcard", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+pub struct DatabaseV0 {
+    pub enabled: Vec<HashSet<UnitName>>,
+    pub levels: Vec<Level>,
+    pub unit_infos: HashMap<UnitName, UnitInfoV0>,
+    pub units: HashMap<UnitName, DbUnitV0>,
+}
+
+impl From<UnitKindV0> for UnitKind {
+    fn from(kind: UnitKindV0) -> Self {
+        match kind {
+            UnitKindV0::Oneshot => Self::Oneshot,
+            UnitKindV0::Daemon => Self::Daemon,
+        }
+    }
+}
+
+impl From<SupervisorV0> for Supervisor {
+    fn from(opts: SupervisorV0) -> Self {
+        Self {
+            cmd: opts.cmd,
+            args: opts.args,
+            restart_delay: opts.restart_delay,
+            restart_attempts: opts.restart_attempts,
+            restart_policy: opts.restart_policy,
+            pwd: opts.pwd,
+            root: opts.root,
+            env: opts.env,
+            group: opts.group,
+            user: opts.user,
+            stdout: opts.stdout,
+            stderr: opts.stderr,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<DependenciesV0> for Dependencies {
+    fn from(deps: DependenciesV0) -> Self {
+        Self {
+            needs: deps.needs,
+            uses: deps.uses,
+            wants: deps.wants,
+            before: deps.before,
+            after: deps.after,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<UnitInfoV0> for UnitInfo {
+    fn from(info: UnitInfoV0) -> Self {
+        Self {
+            name: info.name,
+            source: None,
+            dependencies: Rc::new(Rc::unwrap_or_clone(info.dependencies).into()),
+        }
+    }
+}
+
+impl From<DbUnitV0> for DbUnit {
+    fn from(unit: DbUnitV0) -> Self {
+        DbUnit::new(
+            unit.name,
+            unit.kind.into(),
+            unit.description,
+            Dependencies {
+                needs: unit.needs,
+                uses: unit.uses,
+                wants: unit.wants,
+                before: unit.before,
+                after: unit.after,
+                ..Default::default()
+            },
+            unit.supervisor_opts.into(),
+        )
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::{HashMap, HashSet};
-use std::mem;
+use std::rc::Rc;
 
 #[cfg(feature = "postcard")]
 use postcard::{from_bytes, to_stdvec};
@@ -8,27 +8,107 @@ use rkyv::ser::serializers::AllocSerializer;
 #[cfg(feature = "rkyv")]
 use rkyv::ser::Serializer;
 #[cfg(feature = "rkyv")]
-use rkyv::{from_bytes, Archive, Deserialize, Serialize};
+use rkyv::{from_bytes, AlignedVec, Archive, Deserialize, Serialize};
 #[cfg(feature = "postcard")]
 use serde::{Deserialize, Serialize};
 
+pub use condition::{Conditions, Host};
 #[cfg(feature = "rkyv")]
 use kanit_common::error::StaticError;
 use kanit_common::error::{Context, Result, WithError};
 use kanit_unit::{wrap_unit, RcUnit, UnitInfo, UnitName};
-pub use unit::DbUnit;
+pub use unit::{DbUnit, UnitKind};
 
 use crate::loader::obtain_load_order;
 
+mod condition;
+#[cfg(feature = "serde")]
+pub mod dropin;
+mod legacy;
 mod unit;
 
+/// Version of the database layout, bumped whenever the layout changes.
+/// Databases without a header predate versioning and are migrated on load.
+pub const DB_VERSION: u32 = 1;
+
+const DB_MAGIC: &[u8; 4] = b"KANB";
+
+/// Finds the units conflicting with `name`, regardless of which side declared the conflict.
+pub fn conflicts_with(map: &HashMap<UnitName, UnitInfo>, name: &UnitName) -> HashSet<UnitName> {
+    map.iter()
+        .filter(|(n, info)| *n == name || info.dependencies.conflicts.contains(name))
+        .flat_map(|(n, info)| {
+            if n == name {
+                info.dependencies.conflicts.clone()
+            } else {
+                vec![n.clone()]
+            }
+        })
+        .collect()
+}
+
+/// Resolves a dependency to a unit, either by its name or a virtual name provided by one unit.
+/// Providers within `preferred` are picked over other units.
+fn resolve(
+    map: &HashMap<UnitName, UnitInfo>,
+    preferred: &HashSet<UnitName>,
+    name: &UnitName,
+) -> Result<Option<UnitName>> {
+    if map.contains_key(name) {
+        return Ok(Some(name.clone()));
+    }
+
+    let mut providers = map
+        .iter()
+        .filter(|(_, info)| info.dependencies.provides.contains(name))
+        .map(|(n, _)| n.clone())
+        .collect::<Vec<_>>();
+
+    if providers.iter().any(|p| preferred.contains(p)) {
+        providers.retain(|p| preferred.contains(p));
+    }
+
+    if providers.len() > 1 {
+        providers.sort();
+
+        let name = name.clone();
+        let providers = providers
+            .iter()
+            .map(|p| format!("`{}`", p))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        Err(WithError::with(move || {
+            format!("`{}` is provided by multiple units: {}", name, providers)
+        }))?;
+    }
+
+    Ok(providers.pop())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "rkyv", derive(Archive))]
 #[cfg_attr(feature = "rkyv", archive(check_bytes))]
 pub struct Level(Vec<Vec<UnitName>>);
 
 impl Level {
-    pub fn build(map: &HashMap<UnitName, UnitInfo>, enabled: &HashSet<UnitName>) -> Result<Self> {
+    /// Builds the start order of the `enabled` units and their dependencies. Virtual names are
+    /// resolved to the providers within `preferred` first, usually the units enabled at this
+    /// level or below.
+    pub fn build(
+        map: &HashMap<UnitName, UnitInfo>,
+        enabled: &HashSet<UnitName>,
+        preferred: &HashSet<UnitName>,
+        masked: &HashSet<UnitName>,
+    ) -> Result<Self> {
+        // masked units are treated as if they didn't exist
+        let all = map;
+        let map = &map
+            .iter()
+            .filter(|s| !masked.contains(s.0))
+            .map(|(n, info)| (n.clone(), info.clone()))
+            .collect::<HashMap<_, _>>();
+
         let enabled_services = map
             .iter()
             .filter(|s| enabled.contains(s.0))
@@ -44,35 +124,74 @@ impl Level {
             .map(|s| s.0.clone())
             .collect::<HashSet<_>>();
 
-        let needs = deps.iter().flat_map(|d| &d.needs).collect::<HashSet<_>>();
+        for (unit, info) in enabled_services.iter() {
+            for need in info.dependencies.needs.iter() {
+                if let Some(need) = resolve(map, preferred, need)? {
+                    to_load.insert(need);
+                } else if resolve(all, preferred, need).is_ok_and(|n| n.is_some()) {
+                    let unit = (*unit).clone();
+                    let need = need.clone();
+
+                    Err(WithError::with(move || {
+                        format!("unit `{}` needs `{}` which is masked", unit, need)
+                    }))?;
+                } else {
+                    let need = need.clone();
+
+                    Err(WithError::with(move || {
+                        format!("failed to find dependency `{}`", need)
+                    }))?;
+                }
+            }
+        }
+
+        for want in deps.iter().flat_map(|d| &d.wants) {
+            to_load.extend(resolve(map, preferred, want)?);
+        }
 
-        for need in needs {
-            if !map.contains_key(need) {
-                let need = need.clone();
+        for unit in to_load.iter() {
+            if let Some(other) = conflicts_with(map, unit)
+                .into_iter()
+                .find(|c| to_load.contains(c))
+            {
+                let unit = unit.clone();
 
                 Err(WithError::with(move || {
-                    format!("failed to find dependency `{}`", need)
+                    format!("unit `{}` conflicts with `{}`", unit, other)
                 }))?;
             }
-
-            to_load.insert(need.clone());
         }
 
-        to_load.extend(
-            deps.iter()
-                .flat_map(|d| d.wants.clone())
-                .filter(|d| map.contains_key(d)),
-        );
-
         // only `needs` and `wants` put explicit dependency bounds
         // `uses` is just a recommendation
         // `before`, `after` are just recommendations
 
-        // unwrap: to_load has been checked to exist in map
+        let resolve_all = |names: &[UnitName]| {
+            names
+                .iter()
+                .map(|n| Ok(resolve(map, &to_load, n)?.unwrap_or_else(|| n.clone())))
+                .collect::<Result<Vec<_>>>()
+        };
+
+        // dependencies on virtual names are replaced by their providers before sorting
         let units = to_load
             .iter()
-            .map(|n| (*map.get(n).unwrap()).clone())
-            .collect::<Vec<_>>();
+            .map(|n| {
+                // unwrap: to_load has been checked to exist in map
+                let info = map.get(n).unwrap();
+                let mut deps = (*info.dependencies).clone();
+
+                deps.needs = resolve_all(&deps.needs)?;
+                deps.wants = resolve_all(&deps.wants)?;
+                deps.before = resolve_all(&deps.before)?;
+                deps.after = resolve_all(&deps.after)?;
+
+                Ok(UnitInfo {
+                    dependencies: Rc::new(deps),
+                    ..info.clone()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         let order = obtain_load_order(units)?
             .iter()
@@ -92,6 +211,8 @@ impl Level {
 #[cfg_attr(feature = "rkyv", archive(check_bytes))]
 pub struct Database {
     pub enabled: Vec<HashSet<UnitName>>,
+    /// Units which can't be started, whether enabled or depended on.
+    pub masked: HashSet<UnitName>,
     pub levels: Vec<Level>,
     pub unit_infos: HashMap<UnitName, UnitInfo>,
     pub units: HashMap<UnitName, DbUnit>,
@@ -103,41 +224,45 @@ impl Database {
         services: HashMap<UnitName, RcUnit>,
         enabled: Vec<HashSet<UnitName>>,
     ) -> Result<Self> {
-        let mut levels = vec![];
-
         let unit_infos = services
             .iter()
             .map(|n| (n.0.clone(), UnitInfo::new(n.1)))
             .collect::<HashMap<_, _>>();
 
-        for level in enabled.iter() {
-            levels.push(Level::build(&unit_infos, level)?);
-        }
-
-        Ok(Self {
+        let mut db = Self {
             unit_infos,
             enabled,
-            levels,
+            masked: HashSet::new(),
+            levels: vec![],
             units,
-        })
+        };
+
+        db.rebuild_levels()?;
+
+        Ok(db)
     }
 
     pub fn rebuild_levels(&mut self) -> Result<()> {
         self.levels = vec![];
 
+        // units enabled at lower levels are already running when a level starts
+        let mut below = HashSet::new();
+
         for level in self.enabled.iter() {
-            self.levels.push(Level::build(&self.unit_infos, level)?);
+            below.extend(level.iter().cloned());
+
+            self.levels
+                .push(Level::build(&self.unit_infos, level, &below, &self.masked)?);
         }
 
         Ok(())
     }
 
-    pub fn get_base_map(&mut self) -> HashMap<UnitName, RcUnit> {
-        let units = mem::take(&mut self.units);
-
-        units
-            .into_iter()
-            .map(|u| (u.0.clone(), wrap_unit(u.1)))
+    // units are kept so the database can be written back as loaded
+    pub fn get_base_map(&self) -> HashMap<UnitName, RcUnit> {
+        self.units
+            .iter()
+            .map(|(name, unit)| (name.clone(), wrap_unit(unit.clone())))
             .collect()
     }
 
@@ -161,8 +286,141 @@ impl Database {
         }
     }
 
-    #[cfg(feature = "rkyv")]
+    /// Units of a level along with the indices of the units each has to wait on.
+    /// Only dependencies on earlier groups are kept, so edges dropped to break cycles stay dropped.
+    pub fn get_graph(
+        &self,
+        map: &HashMap<UnitName, RcUnit>,
+        level: usize,
+    ) -> (Vec<RcUnit>, Vec<Vec<usize>>) {
+        let units = self
+            .levels
+            .get(level)
+            .map(|l| {
+                l.0.iter()
+                    .enumerate()
+                    .flat_map(|(g, n)| n.iter().map(move |m| (g, m)))
+                    .filter(|(_, m)| map.contains_key(*m))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        // virtual names are resolved to their providers within the level, as when it was built
+        let in_level = units
+            .iter()
+            .map(|(_, n)| (*n).clone())
+            .collect::<HashSet<_>>();
+
+        let resolve_all = |names: &[UnitName]| {
+            names
+                .iter()
+                .map(|n| {
+                    resolve(&self.unit_infos, &in_level, n)
+                        .ok()
+                        .flatten()
+                        .unwrap_or_else(|| n.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // units each waits on and units each comes before
+        let deps = units
+            .iter()
+            .map(|(_, n)| {
+                self.unit_infos
+                    .get(*n)
+                    .map(|i| {
+                        let d = &i.dependencies;
+
+                        (
+                            [
+                                resolve_all(&d.needs),
+                                resolve_all(&d.wants),
+                                resolve_all(&d.after),
+                            ]
+                            .concat(),
+                            resolve_all(&d.before),
+                        )
+                    })
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let waits = units
+            .iter()
+            .enumerate()
+            .map(|(i, (g, a))| {
+                units
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, (h, b))| {
+                        h < g && (deps[i].0.contains(b) || deps[*j].1.contains(a))
+                    })
+                    .map(|(j, _)| j)
+                    .collect()
+            })
+            .collect();
+
+        // unwrap: units were filtered to exist in map
+        let units = units
+            .into_iter()
+            .map(|(_, n)| map.get(n).unwrap().clone())
+            .collect();
+
+        (units, waits)
+    }
+
+    /// Serializes the database behind a header holding its version.
     pub fn dump(&self) -> Result<Vec<u8>> {
+        let mut bytes = DB_MAGIC.to_vec();
+
+        bytes.extend(DB_VERSION.to_le_bytes());
+        bytes.extend(self.encode()?);
+
+        Ok(bytes)
+    }
+
+    pub fn load(bytes: &[u8]) -> Result<Self> {
+        let Some(rest) = bytes.strip_prefix(DB_MAGIC) else {
+            return Self::migrate(decode_legacy(bytes)?);
+        };
+
+        let (version, payload) = rest
+            .split_first_chunk::<4>()
+            .context("failed to read database version")?;
+
+        match u32::from_le_bytes(*version) {
+            DB_VERSION => Self::decode(payload),
+            version => Err(WithError::with(move || {
+                format!("unsupported database version {}", version)
+            }))?,
+        }
+    }
+
+    fn migrate(old: legacy::DatabaseV0) -> Result<Self> {
+        let mut db = Self {
+            enabled: old.enabled,
+            masked: HashSet::new(),
+            levels: vec![],
+            unit_infos: old
+                .unit_infos
+                .into_iter()
+                .map(|(n, info)| (n, info.into()))
+                .collect(),
+            units: old
+                .units
+                .into_iter()
+                .map(|(n, unit)| (n, unit.into()))
+                .collect(),
+        };
+
+        db.rebuild_levels()?;
+
+        Ok(db)
+    }
+
+    #[cfg(feature = "rkyv")]
+    fn encode(&self) -> Result<Vec<u8>> {
         let mut serializer = AllocSerializer::<512>::default();
         serializer
             .serialize_value(self)
@@ -173,17 +431,259 @@ impl Database {
     }
 
     #[cfg(feature = "rkyv")]
-    pub fn load(bytes: &[u8]) -> Result<Self> {
-        Ok(from_bytes(bytes).map_err(|_| StaticError("failed to deserialize database"))?)
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(from_bytes(&aligned(bytes))
+            .map_err(|_| StaticError("failed to deserialize database"))?)
     }
 
     #[cfg(feature = "postcard")]
-    pub fn dump(&self) -> Result<Vec<u8>> {
+    fn encode(&self) -> Result<Vec<u8>> {
         to_stdvec(self).context("failed to serialize database")
     }
 
     #[cfg(feature = "postcard")]
-    pub fn load(bytes: &[u8]) -> Result<Self> {
+    fn decode(bytes: &[u8]) -> Result<Self> {
         from_bytes(bytes).context("failed to deserialize database")
     }
 }
+
+// archived data has to be aligned, which the header may have broken
+#[cfg(feature = "rkyv")]
+fn aligned(bytes: &[u8]) -> AlignedVec {
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    aligned
+}
+
+#[cfg(feature = "rkyv")]
+fn decode_legacy(bytes: &[u8]) -> Result<legacy::DatabaseV0> {
+    Ok(from_bytes(&aligned(bytes)).map_err(|_| StaticError("failed to deserialize database"))?)
+}
+
+#[cfg(feature = "postcard")]
+fn decode_legacy(bytes: &[u8]) -> Result<legacy::DatabaseV0> {
+    from_bytes(bytes).context("failed to deserialize database")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use async_trait::async_trait;
+
+    use kanit_unit::{wrap_unit, Dependencies, Unit};
+
+    use super::*;
+
+    struct Named(UnitName);
+
+    #[async_trait]
+    impl Unit for Named {
+        fn name(&self) -> UnitName {
+            self.0.clone()
+        }
+
+        async fn start(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn info(name: &str, dependencies: &Dependencies) -> (UnitName, UnitInfo) {
+        (
+            UnitName::from(name),
+            UnitInfo {
+                name: UnitName::from(name),
+                source: None,
+                dependencies: Rc::new(dependencies.clone()),
+            },
+        )
+    }
+
+    #[cfg(feature = "rkyv")]
+    fn encode_legacy(db: &legacy::DatabaseV0) -> Vec<u8> {
+        let mut serializer = AllocSerializer::<512>::default();
+        serializer.serialize_value(db).unwrap();
+        serializer.into_serializer().into_inner().to_vec()
+    }
+
+    #[cfg(feature = "postcard")]
+    fn encode_legacy(db: &legacy::DatabaseV0) -> Vec<u8> {
+        to_stdvec(db).unwrap()
+    }
+
+    #[cfg(feature = "rkyv")]
+    fn legacy_unit(name: &str) -> legacy::DbUnitV0 {
+        legacy::DbUnitV0 {
+            name: UnitName::from(name),
+            kind: legacy::UnitKindV0::Daemon,
+            description: Some(UnitName::from("a daemon")),
+            before: vec![],
+            after: vec![],
+            needs: vec![UnitName::from("syslog")],
+            uses: vec![],
+            wants: vec![],
+            supervisor_opts: legacy::SupervisorV0 {
+                cmd: "/bin/daemon".to_string(),
+                restart_delay: Some(2),
+                ..Default::default()
+            },
+            pid: 0,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let db = Database {
+            enabled: vec![HashSet::from([UnitName::from("syslog")])],
+            masked: HashSet::from([UnitName::from("getty")]),
+            levels: vec![],
+            unit_infos: HashMap::from([info("syslog", &Dependencies::new())]),
+            units: HashMap::new(),
+        };
+
+        let loaded = Database::load(&db.dump().unwrap()).unwrap();
+
+        assert_eq!(loaded.enabled, db.enabled);
+        assert_eq!(loaded.masked, db.masked);
+        assert!(loaded.unit_infos.contains_key(&UnitName::from("syslog")));
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut bytes = DB_MAGIC.to_vec();
+        bytes.extend((DB_VERSION + 1).to_le_bytes());
+
+        assert!(Database::load(&bytes).is_err());
+    }
+
+    #[test]
+    fn migrate_unversioned() {
+        let daemon = UnitName::from("daemon");
+
+        let old = legacy::DatabaseV0 {
+            enabled: vec![
+                HashSet::from([UnitName::from("syslog")]),
+                HashSet::from([daemon.clone()]),
+            ],
+            levels: vec![],
+            unit_infos: HashMap::from([
+                (
+                    UnitName::from("syslog"),
+                    legacy::UnitInfoV0 {
+                        name: UnitName::from("syslog"),
+                        dependencies: Rc::default(),
+                    },
+                ),
+                (
+                    daemon.clone(),
+                    legacy::UnitInfoV0 {
+                        name: daemon.clone(),
+                        dependencies: Rc::new(legacy::DependenciesV0 {
+                            needs: vec![UnitName::from("syslog")],
+                            ..Default::default()
+                        }),
+                    },
+                ),
+            ]),
+            units: HashMap::new(),
+        };
+
+        let db = Database::load(&encode_legacy(&old)).unwrap();
+
+        assert_eq!(db.enabled, old.enabled);
+        assert_eq!(db.levels.len(), 2);
+        assert!(db.levels[1].get_order().concat().contains(&daemon));
+    }
+
+    // postcard can't serialize the flattened options of units
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn migrate_unversioned_units() {
+        let daemon = UnitName::from("daemon");
+
+        let old = legacy::DatabaseV0 {
+            enabled: vec![],
+            levels: vec![],
+            unit_infos: HashMap::new(),
+            units: HashMap::from([(daemon.clone(), legacy_unit("daemon"))]),
+        };
+
+        let db = Database::load(&encode_legacy(&old)).unwrap();
+        let unit = &db.units[&daemon];
+
+        assert_eq!(unit.kind, UnitKind::Daemon);
+        assert_eq!(unit.needs, [UnitName::from("syslog")]);
+        assert_eq!(unit.supervisor_opts.cmd, "/bin/daemon");
+        assert_eq!(unit.supervisor_opts.restart_delay, Some(2));
+
+        // the loader maps units before writing migrated databases back
+        assert!(db.get_base_map().contains_key(&daemon));
+
+        let written = Database::load(&db.dump().unwrap()).unwrap();
+
+        assert!(written.units.contains_key(&daemon));
+    }
+
+    #[test]
+    fn provider_from_lower_level() {
+        let logger = UnitName::from("logger");
+
+        let mut db = Database {
+            enabled: vec![
+                HashSet::from([UnitName::from("syslog")]),
+                HashSet::new(),
+                HashSet::from([UnitName::from("app")]),
+            ],
+            masked: HashSet::new(),
+            levels: vec![],
+            unit_infos: HashMap::from([
+                info("syslog", Dependencies::new().provide(logger.clone())),
+                info("other-logger", Dependencies::new().provide(logger.clone())),
+                info("app", Dependencies::new().need(logger)),
+            ]),
+            units: HashMap::new(),
+        };
+
+        db.rebuild_levels().unwrap();
+
+        let level = db.levels[2].get_order().concat();
+
+        assert!(level.contains(&UnitName::from("syslog")));
+        assert!(!level.contains(&UnitName::from("other-logger")));
+    }
+
+    #[test]
+    fn graph_waits_on_provider() {
+        let logger = UnitName::from("logger");
+        let syslog = UnitName::from("syslog");
+        let app = UnitName::from("app");
+
+        let mut db = Database {
+            enabled: vec![HashSet::from([syslog.clone(), app.clone()])],
+            masked: HashSet::new(),
+            levels: vec![],
+            unit_infos: HashMap::from([
+                info("syslog", Dependencies::new().provide(logger.clone())),
+                info("app", Dependencies::new().need(logger)),
+            ]),
+            units: HashMap::new(),
+        };
+
+        db.rebuild_levels().unwrap();
+
+        let map = [&syslog, &app]
+            .into_iter()
+            .map(|n| (n.clone(), wrap_unit(Named(n.clone()))))
+            .collect::<HashMap<_, _>>();
+
+        let (units, waits) = db.get_graph(&map, 0);
+        let index = |name: &UnitName| units.iter().position(|u| u.borrow().name() == *name);
+
+        // unwrap: both units are in the level
+        let syslog = index(&syslog).unwrap();
+        let app = index(&app).unwrap();
+
+        assert_eq!(waits[app], vec![syslog]);
+        assert!(waits[syslog].is_empty());
+    }
+}
@@ -1,15 +1,18 @@
+use std::os::fd::{AsRawFd, OwnedFd};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use async_trait::async_trait;
-use nix::sys::signal::{kill, Signal};
-use nix::unistd::Pid;
 #[cfg(feature = "rkyv")]
 use rkyv::Archive;
 
-use kanit_common::error::{Context, ErrorKind, Result};
+use kanit_common::error::{Context, Result};
 use kanit_supervisor::{RestartPolicy, Supervisor};
-use kanit_unit::supervisor::SupervisorBuilder;
-use kanit_unit::{Dependencies, Unit, UnitInfo, UnitName};
+use kanit_unit::socket;
+use kanit_unit::supervisor::{self, SupervisorBuilder};
+use kanit_unit::{Dependencies, Preparation, Unit, UnitInfo, UnitName, UnitNameExt};
+
+use crate::db::{Conditions, Host};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -19,6 +22,8 @@ use kanit_unit::{Dependencies, Unit, UnitInfo, UnitName};
 pub enum UnitKind {
     Oneshot,
     Daemon,
+    /// A synchronization point without a process, such as `network-online`.
+    Target,
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +34,9 @@ pub struct DbUnit {
     pub name: UnitName,
     pub kind: UnitKind,
     pub description: Option<UnitName>,
+    // set when the unit is enabled
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub source: Option<String>,
     #[cfg_attr(feature = "serde", serde(default))]
     pub before: Vec<UnitName>,
     #[cfg_attr(feature = "serde", serde(default))]
@@ -39,16 +47,93 @@ pub struct DbUnit {
     pub uses: Vec<UnitName>,
     #[cfg_attr(feature = "serde", serde(default))]
     pub wants: Vec<UnitName>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub conflicts: Vec<UnitName>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub provides: Vec<UnitName>,
+    /// Sockets bound by init and passed to the unit, such as `tcp:127.0.0.1:8080`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub listen: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub conditions: Conditions,
     #[cfg_attr(feature = "serde", serde(flatten))]
     pub supervisor_opts: Supervisor,
     #[cfg_attr(feature = "serde", serde(skip))]
     pid: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    sockets: Arc<Vec<OwnedFd>>,
 }
 
 impl DbUnit {
+    pub fn new(
+        name: UnitName,
+        kind: UnitKind,
+        description: Option<UnitName>,
+        dependencies: Dependencies,
+        supervisor_opts: Supervisor,
+    ) -> Self {
+        Self {
+            name,
+            kind,
+            description,
+            source: None,
+            before: dependencies.before,
+            after: dependencies.after,
+            needs: dependencies.needs,
+            uses: dependencies.uses,
+            wants: dependencies.wants,
+            conflicts: dependencies.conflicts,
+            provides: dependencies.provides,
+            listen: vec![],
+            conditions: Conditions::default(),
+            supervisor_opts,
+            pid: 0,
+            sockets: Arc::default(),
+        }
+    }
+
+    /// Turns a template into the instance `name`, replacing `%i` in its command and paths.
+    pub fn instantiate(&mut self, name: UnitName) -> Result<()> {
+        let instance = name
+            .instance()
+            .context("expected an instance name")?
+            .to_string();
+        let sub = |s: &mut String| *s = s.replace("%i", &instance);
+
+        let opts = &mut self.supervisor_opts;
+
+        sub(&mut opts.cmd);
+        opts.args.iter_mut().for_each(sub);
+        opts.env.iter_mut().for_each(sub);
+        opts.environment_file.iter_mut().for_each(sub);
+
+        [
+            &mut opts.pwd,
+            &mut opts.root,
+            &mut opts.stdout,
+            &mut opts.stderr,
+            &mut opts.pid_file,
+            &mut opts.tty_path,
+        ]
+        .into_iter()
+        .flatten()
+        .for_each(sub);
+
+        self.listen.iter_mut().for_each(sub);
+        self.description = self
+            .description
+            .as_ref()
+            .map(|d| UnitName::from(d.replace("%i", &instance)));
+        self.name = name;
+
+        Ok(())
+    }
+
     pub fn get_unit_info(&self) -> UnitInfo {
         UnitInfo {
             name: self.name.clone(),
+            source: self.source.clone(),
             dependencies: Rc::new(self.dependencies()),
         }
     }
@@ -64,6 +149,10 @@ impl Unit for DbUnit {
         self.description.as_deref()
     }
 
+    fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
     fn dependencies(&self) -> Dependencies {
         let mut deps = Dependencies::new();
 
@@ -82,16 +171,55 @@ impl Unit for DbUnit {
         self.wants.iter().for_each(|b| {
             deps.want(b.clone());
         });
+        self.conflicts.iter().for_each(|b| {
+            deps.conflict(b.clone());
+        });
+        self.provides.iter().for_each(|b| {
+            deps.provide(b.clone());
+        });
 
         deps
     }
 
+    fn pid(&self) -> Option<u32> {
+        (self.pid != 0).then_some(self.pid)
+    }
+
+    fn listen(&mut self) -> Result<()> {
+        // sockets stay bound across restarts
+        if self.sockets.is_empty() && !self.listen.is_empty() {
+            self.sockets = Arc::new(
+                self.listen
+                    .iter()
+                    .map(|spec| socket::bind(spec))
+                    .collect::<Result<_>>()?,
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn prepare(&self) -> Result<Preparation> {
+        Ok(self
+            .conditions
+            .unmet(&Host::detect())
+            .map_or(Preparation::Ready, Preparation::Skip))
+    }
+
     async fn start(&mut self) -> Result<()> {
+        if self.kind == UnitKind::Target {
+            return Ok(());
+        }
+
         if self.kind == UnitKind::Oneshot {
             self.supervisor_opts.restart_policy = Some(RestartPolicy::OnFailure);
         }
 
-        let child = SupervisorBuilder::from_supervisor(self.supervisor_opts.clone()).spawn()?;
+        let child = SupervisorBuilder::from_supervisor(self.supervisor_opts.clone())
+            .name(self.name.to_string())
+            .sockets(self.sockets.iter().map(|s| s.as_raw_fd()).collect())
+            .spawn()
+            .await?;
 
         self.pid = child.id();
 
@@ -99,7 +227,16 @@ impl Unit for DbUnit {
     }
 
     async fn stop(&mut self) -> Result<()> {
-        kill(Pid::from_raw(self.pid as i32), Signal::SIGKILL)
-            .context_kind("failed to stop supervisor", ErrorKind::Recoverable)
+        if self.kind == UnitKind::Target || self.pid == 0 {
+            return Ok(());
+        }
+
+        // the supervisor stops the service it is tracking before exiting
+        supervisor::stop(self.pid).await?;
+
+        // the pid may be reused once the supervisor was reaped
+        self.pid = 0;
+
+        Ok(())
     }
 }
@@ -1,18 +1,103 @@
 use std::collections::HashSet;
+use std::os::unix::process::ExitStatusExt;
 
-use log::warn;
+use async_lock::Mutex;
+use log::{info, warn};
 
-use kanit_common::error::{Context, Result, StaticError};
-use kanit_unit::UnitName;
+use kanit_common::error::{Context, Result, StaticError, WithError};
+use kanit_control::{Request, Response, Status};
+use kanit_executor::process;
+use kanit_unit::{Preparation, Unit, UnitName};
 
-use crate::loader::Loader;
+use crate::db::conflicts_with;
+#[cfg(feature = "serde")]
+use crate::loader::apply_dropins;
+use crate::loader::{Loader, StateTable};
 
-async fn modify_service(start: bool, level: usize, name: &[u8]) -> Result<()> {
+pub(crate) static EV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Starts a unit unless a unit it needs didn't start, tracking its state.
+/// Returns whether the unit started, units which didn't are added to `failed`.
+async fn start_tracked(
+    states: &StateTable,
+    unit: &mut dyn Unit,
+    failed: &mut HashSet<UnitName>,
+) -> Result<bool> {
+    let name = unit.name();
+
+    states.activating(name.clone());
+
+    if let Some(need) = unit
+        .dependencies()
+        .needs
+        .iter()
+        .find(|n| failed.contains(*n))
+    {
+        let reason = format!("needs `{}` which did not start", need);
+
+        warn!("skipping {}, {}", name, reason);
+        states.skipped(name.clone(), reason);
+
+        failed.insert(name);
+        failed.extend(unit.dependencies().provides);
+
+        return Ok(false);
+    }
+
+    let res = match unit.listen() {
+        Ok(_) => unit.prepare().await,
+        Err(e) => Err(e),
+    };
+
+    let res = match res {
+        Ok(Preparation::Ready) => unit.start().await.map(|_| true),
+        Ok(Preparation::Skip(reason)) => {
+            info!("skipping {}, {}", name, reason);
+            states.skipped(name.clone(), reason);
+
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    };
+
+    match res {
+        Ok(true) => states.active(unit),
+        Ok(false) => {}
+        Err(ref e) => {
+            warn!("{}", e);
+            states.failed(name.clone(), e);
+        }
+    }
+
+    if !matches!(res, Ok(true)) {
+        failed.insert(name);
+        failed.extend(unit.dependencies().provides);
+    }
+
+    res
+}
+
+async fn stop_tracked(states: &StateTable, unit: &mut dyn Unit) -> Result<()> {
+    states.deactivating(unit.name());
+
+    if let Err(e) = unit.stop().await {
+        warn!("{}", e);
+        states.failed(unit.name(), &e);
+
+        return Err(e);
+    }
+
+    states.inactive(unit.name());
+
+    Ok(())
+}
+
+async fn modify_service(start: bool, level: usize, name: &str) -> Result<()> {
     // this is horrible but it makes the compiler happy
-    let (diff, groups) = {
+    let (diff, groups, conflicting) = {
         let loader = Loader::obtain()?.borrow();
 
-        let unit_name = UnitName::from(String::from_utf8_lossy(name));
+        let unit_name = UnitName::from(name);
 
         if (start && loader.is_started(level, &unit_name))
             || (!start && !loader.is_started(level, &unit_name))
@@ -23,6 +108,26 @@ async fn modify_service(start: bool, level: usize, name: &[u8]) -> Result<()> {
         // rebuild database and diff to find out what needs to start
         let mut db = loader.database().clone();
 
+        if start && db.masked.contains(&unit_name) {
+            let unit_name = unit_name.clone();
+
+            Err(WithError::with(move || {
+                format!("unit `{}` is masked", unit_name)
+            }))?;
+        }
+
+        // conflicting units are replaced by the started unit
+        let conflicting = if start {
+            conflicts_with(&db.unit_infos, &unit_name)
+        } else {
+            HashSet::new()
+        };
+
+        // conflicting units may be enabled at any level
+        for enabled in db.enabled.iter_mut() {
+            enabled.retain(|u| !conflicting.contains(u));
+        }
+
         {
             let enabled = db.enabled.get_mut(level).context("failed to get level")?;
 
@@ -61,29 +166,47 @@ async fn modify_service(start: bool, level: usize, name: &[u8]) -> Result<()> {
             .context("failed to get level")?
             .get_order();
 
-        (diff, groups.clone())
+        // stopped from the highest level down, like a level switch
+        let conflicting = loader
+            .started
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(l, started)| {
+                started
+                    .intersection(&conflicting)
+                    .map(move |n| (l, n.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        (diff, groups.clone(), conflicting)
     };
 
     let mut loader = Loader::obtain()?.borrow_mut();
+    let states = loader.states.clone();
+
+    let mut stopped = HashSet::new();
+
+    for (l, unit_n) in conflicting {
+        // a unit may be marked started in more than one level
+        if stopped.insert(unit_n.clone()) {
+            let unit = loader.get_unit(&unit_n).context("failed to get unit")?;
+
+            stop_tracked(&states, &mut *unit.borrow_mut()).await?;
+        }
+
+        loader.mark_stopped(l, &unit_n);
+    }
 
     if start {
+        let mut failed = HashSet::new();
+
         for group in groups {
             for unit_n in group.iter().filter(|u| diff.contains(*u)) {
                 let unit = loader.get_unit(unit_n).context("failed to get unit")?;
 
-                let mut unit_b = unit.borrow_mut();
-
-                if !unit_b.prepare().await? {
-                    warn!("failed preparations for {}", unit_b.name());
-
-                    continue;
-                }
-
-                if let Err(e) = unit_b.start().await {
-                    warn!("{}", e);
-                    return Err(e);
-                } else {
-                    loader.mark_started(level, unit_b.name());
+                if start_tracked(&states, &mut *unit.borrow_mut(), &mut failed).await? {
+                    loader.mark_started(level, unit_n.clone());
                 }
             }
         }
@@ -92,14 +215,9 @@ async fn modify_service(start: bool, level: usize, name: &[u8]) -> Result<()> {
             for unit_n in group.iter().filter(|u| diff.contains(*u)) {
                 let unit = loader.get_unit(unit_n).context("failed to get unit")?;
 
-                let mut unit_b = unit.borrow_mut();
+                stop_tracked(&states, &mut *unit.borrow_mut()).await?;
 
-                if let Err(e) = unit_b.stop().await {
-                    warn!("{}", e);
-                    return Err(e);
-                } else {
-                    loader.mark_stopped(level, &unit_b.name());
-                }
+                loader.mark_stopped(level, unit_n);
             }
         }
     }
@@ -107,38 +225,202 @@ async fn modify_service(start: bool, level: usize, name: &[u8]) -> Result<()> {
     Ok(())
 }
 
-pub async fn event(data: Vec<u8>) -> Result<()> {
-    if data.starts_with(b"db-reload") {
-        let mut loader = Loader::obtain()?.borrow_mut();
+async fn switch_level(target: Option<usize>) -> Result<()> {
+    let mut loader = Loader::obtain()?.borrow_mut();
 
-        let ev_lock = loader.ev_lock.clone();
+    if target.is_some_and(|t| t >= loader.get_levels()) {
+        Err(StaticError("level does not exist"))?;
+    }
 
-        let lock = ev_lock.lock().await;
+    let current = loader.level;
+    let states = loader.states.clone();
+    let orders = (0..loader.get_levels())
+        .map(|l| loader.database().levels[l].get_order().clone())
+        .collect::<Vec<_>>();
+
+    // units of the target level and below keep running
+    let keep = orders
+        .iter()
+        .take(target.map_or(0, |t| t + 1))
+        .flatten()
+        .flatten()
+        .cloned()
+        .collect::<HashSet<_>>();
+
+    if let Some(current) = current.filter(|c| target.is_none_or(|t| *c > t)) {
+        let above = target.map_or(0, |t| t + 1);
+
+        for level in (above..=current).rev() {
+            for group in orders[level].iter().rev() {
+                for unit_n in group.iter() {
+                    if !loader.is_started(level, unit_n) || keep.contains(unit_n) {
+                        continue;
+                    }
+
+                    let unit = loader.get_unit(unit_n).context("failed to get unit")?;
+
+                    // the unit is considered stopped either way
+                    let _ = stop_tracked(&states, &mut *unit.borrow_mut()).await;
+
+                    loader.mark_stopped(level, unit_n);
+                }
+            }
+        }
+    }
 
-        loader.reload()?;
+    if let Some(target) = target.filter(|t| current.is_none_or(|c| c < *t)) {
+        let below = current.map_or(0, |c| c + 1);
+        let mut failed = HashSet::new();
 
-        drop(lock);
-    } else if data.starts_with(b"start") || data.starts_with(b"stop") {
-        // start:tty:1
-        let mut parts = data.split(|b| *b == b':');
+        for (level, order) in orders.iter().enumerate().take(target + 1).skip(below) {
+            for group in order.iter() {
+                for unit_n in group.iter() {
+                    // units can be enabled in several levels
+                    if (0..=level).any(|l| loader.is_started(l, unit_n)) {
+                        continue;
+                    }
 
-        parts.next(); // forward start/stop
+                    let unit = loader.get_unit(unit_n).context("failed to get unit")?;
 
-        let name = parts.next().context("failed to get name")?;
+                    // failures are logged and later units still start
+                    let started =
+                        start_tracked(&states, &mut *unit.borrow_mut(), &mut failed).await;
 
-        let level = String::from_utf8_lossy(parts.next().context("failed to get level")?)
-            .trim()
-            .parse::<usize>()
-            .context("failed to parse level")?;
+                    if let Ok(true) = started {
+                        loader.mark_started(level, unit_n.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    loader.level = target;
 
-        let ev_lock = Loader::obtain()?.borrow().ev_lock.clone();
+    Ok(())
+}
 
-        let lock = ev_lock.lock().await; // get lock to ensure no one else is using the loader
+async fn restart_service(level: usize, name: &str) -> Result<()> {
+    let mut loader = Loader::obtain()?.borrow_mut();
+    let states = loader.states.clone();
 
-        modify_service(data.starts_with(b"start"), level, name).await?;
+    let unit_name = UnitName::from(name);
 
-        drop(lock);
+    if !loader.is_started(level, &unit_name) {
+        Err(StaticError("unit is not started"))?;
+    }
+
+    let unit = loader.get_unit(&unit_name).context("failed to get unit")?;
+
+    let mut unit_b = unit.borrow_mut();
+
+    stop_tracked(&states, &mut *unit_b).await?;
+
+    if !start_tracked(&states, &mut *unit_b, &mut HashSet::new()).await? {
+        loader.mark_stopped(level, &unit_name);
     }
 
     Ok(())
 }
+
+// records the exits of supervisors which have been reaped
+fn record_exits() -> Result<()> {
+    // the loader stays borrowed while a request waits on units, exits are recorded once it's done
+    let states = match Loader::obtain()?.try_borrow() {
+        Ok(loader) => loader.states.clone(),
+        Err(_) => return Ok(()),
+    };
+
+    for (name, pid) in states.running() {
+        if let Some(status) = process::try_wait(pid) {
+            let code = status
+                .code()
+                .unwrap_or_else(|| 128 + status.signal().unwrap_or_default());
+
+            states.exited(name, code);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reaps every exited child, including orphans, and records the exits of supervisors.
+pub fn reap() -> Result<()> {
+    process::reap();
+
+    record_exits()
+}
+
+fn reload() -> Result<()> {
+    let mut loader = Loader::obtain()?.borrow_mut();
+
+    loader.reload()?;
+
+    // drop-ins may have changed along with the database
+    #[cfg(feature = "serde")]
+    apply_dropins(&mut loader)?;
+
+    Ok(())
+}
+
+fn status(unit: Option<&str>) -> Result<Status> {
+    let loader = Loader::obtain()?.borrow();
+
+    let mut seen = HashSet::new();
+
+    // units depended on at several levels are in each of them, but shown once at the lowest
+    let units = (0..loader.get_levels())
+        .flat_map(|l| {
+            loader.database().levels[l]
+                .get_order()
+                .iter()
+                .flatten()
+                .map(move |n| (l, n.clone()))
+        })
+        .filter(|(_, n)| unit.is_none_or(|u| **n == *u))
+        .filter(|(_, n)| seen.insert(n.clone()))
+        .map(|(level, name)| loader.states.get(&name).to_status(&name, level))
+        .collect::<Vec<_>>();
+
+    if unit.is_some() && units.is_empty() {
+        Err(StaticError("failed to find unit"))?;
+    }
+
+    Ok(Status {
+        level: loader.level,
+        units,
+    })
+}
+
+/// Handles a request from the control socket, other than power requests which init handles itself.
+pub async fn event(request: Request) -> Result<Response> {
+    // get lock to ensure no one else is using the loader, which stays borrowed while units are
+    // waited on
+    let lock = EV_LOCK.lock().await;
+
+    let response = match request {
+        Request::Start { unit, level, .. } => modify_service(true, level, &unit)
+            .await
+            .map(|_| Response::Ok),
+        Request::Stop { unit, level, .. } => modify_service(false, level, &unit)
+            .await
+            .map(|_| Response::Ok),
+        Request::Restart { unit, level, .. } => {
+            restart_service(level, &unit).await.map(|_| Response::Ok)
+        }
+        Request::Status { unit } => status(unit.as_deref()).map(Response::Status),
+        Request::Reload => reload().map(|_| Response::Ok),
+        Request::Runlevel { level } => switch_level(level).await.map(|_| Response::Ok),
+        Request::Power(_) | Request::Subscribe { .. } => Err(StaticError(
+            "power requests and subscriptions are handled by init",
+        ))?,
+    };
+
+    drop(lock);
+
+    // supervisors reaped while the request was handled
+    if let Err(e) = record_exits() {
+        warn!("{}", e);
+    }
+
+    response
+}
@@ -4,23 +4,25 @@ use std::fs;
 use std::rc::Rc;
 use std::sync::OnceLock;
 
-use async_lock::Mutex;
 use log::warn;
 use send_wrapper::SendWrapper;
 
 use kanit_common::constants;
 use kanit_common::error::{Context, Result, StaticError};
-use kanit_unit::{RcUnit, UnitName};
+use kanit_unit::{RcUnit, UnitInfo, UnitName};
 
 use crate::db::Database;
+use crate::loader::StateTable;
 
 static LOADER: OnceLock<SendWrapper<RefCell<Loader>>> = OnceLock::new();
 
 pub struct Loader {
     pub defaulted: bool,
     pub started: Vec<HashSet<UnitName>>,
+    /// The level the system is at, `None` until booted or once shut down.
+    pub level: Option<usize>,
     pub map: HashMap<UnitName, RcUnit>,
-    pub ev_lock: Rc<Mutex<()>>, // i am pro at rust
+    pub states: Rc<StateTable>,
     database: Database,
 }
 
@@ -31,14 +33,19 @@ impl Loader {
     {
         let defaulted;
 
-        let mut database = if let Ok(bytes) = fs::read(constants::KAN_DB) {
-            defaulted = true;
-            if let Ok(db) = Database::load(&bytes) {
-                db
-            } else {
-                warn!("failed to load database, using default");
-                // defaulted flag not set to preserve database just in case of recovery
-                default()?
+        let database = if let Ok(bytes) = fs::read(constants::KAN_DB) {
+            match Database::load(&bytes) {
+                Ok(db) => {
+                    // written back so migrated databases are stored with the current layout
+                    defaulted = true;
+                    db
+                }
+                Err(e) => {
+                    warn!("failed to load database, using default: {}", e);
+                    // defaulted flag not set to preserve database just in case of recovery
+                    defaulted = false;
+                    default()?
+                }
             }
         } else {
             defaulted = true;
@@ -52,9 +59,10 @@ impl Loader {
 
         // ignore error since it just returns what we tried to load
         let _ = LOADER.set(SendWrapper::new(RefCell::new(Self {
-            ev_lock: Rc::new(Mutex::new(())),
             defaulted,
             started,
+            level: None,
+            states: Rc::new(StateTable::default()),
             database,
             map,
         })));
@@ -63,13 +71,14 @@ impl Loader {
     }
 
     pub fn reload(&mut self) -> Result<()> {
-        let mut database = if let Ok(bytes) = fs::read(constants::KAN_DB) {
+        let database = if let Ok(bytes) = fs::read(constants::KAN_DB) {
             Database::load(&bytes)?
         } else {
             return Ok(());
         };
 
-        self.map = database.get_base_map();
+        // baked units aren't stored in the database, so they are kept
+        self.map.extend(database.get_base_map());
         self.database = database;
 
         Ok(())
@@ -92,6 +101,25 @@ impl Loader {
         self.map.extend(iter);
     }
 
+    /// Replaces units in the map and rebuilds the levels with their dependencies.
+    pub fn replace_units(&mut self, units: Vec<RcUnit>) -> Result<()> {
+        let mut database = self.database.clone();
+
+        for unit in units {
+            let name = unit.borrow().name();
+
+            database
+                .unit_infos
+                .insert(name.clone(), UnitInfo::new(&unit));
+            self.map.insert(name, unit);
+        }
+
+        database.rebuild_levels()?;
+        self.database = database;
+
+        Ok(())
+    }
+
     pub fn get_levels(&self) -> usize {
         self.database.get_levels()
     }
@@ -100,6 +128,10 @@ impl Loader {
         self.database.get_level(&self.map, level)
     }
 
+    pub fn get_graph(&self, level: usize) -> (Vec<RcUnit>, Vec<Vec<usize>>) {
+        self.database.get_graph(&self.map, level)
+    }
+
     pub fn get_unit(&self, name: &UnitName) -> Option<RcUnit> {
         self.map.iter().for_each(|n| {
             dbg!(n.0);
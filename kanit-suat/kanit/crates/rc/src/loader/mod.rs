@@ -1,14 +1,23 @@
 #[cfg(feature = "units")]
 use std::collections::HashMap;
 
+#[cfg(all(feature = "units", feature = "serde"))]
+use log::warn;
+
 #[cfg(feature = "units")]
 use kanit_common::error::Result;
+#[cfg(all(feature = "units", feature = "serde"))]
+use kanit_unit::wrap_unit;
 #[cfg(feature = "units")]
 use kanit_units::{baked_units, default_levels};
 #[cfg(feature = "units")]
 pub use loader::Loader;
 pub use sort::obtain_load_order;
+#[cfg(feature = "units")]
+pub use state::{observe, StateTable};
 
+#[cfg(all(feature = "units", feature = "serde"))]
+use crate::db::dropin;
 #[cfg(feature = "units")]
 use crate::db::Database;
 
@@ -16,6 +25,8 @@ use crate::db::Database;
 #[cfg(feature = "units")]
 mod loader;
 mod sort;
+#[cfg(feature = "units")]
+mod state;
 
 #[cfg(feature = "units")]
 fn default_database() -> Result<Database> {
@@ -33,12 +44,50 @@ pub fn init_loader() -> Result<()> {
 
     let mut loader = Loader::obtain()?.borrow_mut();
 
+    let baked = baked_units();
+
     loader.extend_map(
-        baked_units()
+        baked
             .iter()
             .map(|n| (n.borrow().name(), n.clone()))
             .collect::<Vec<_>>(),
     );
 
+    #[cfg(feature = "serde")]
+    apply_dropins(&mut loader)?;
+
+    Ok(())
+}
+
+#[cfg(all(feature = "units", feature = "serde"))]
+fn warn_err<T>(patched: Result<Option<T>>) -> Option<T> {
+    patched.unwrap_or_else(|e| {
+        warn!("{}", e);
+        None
+    })
+}
+
+/// Applies the drop-ins of built-in and stored units. Stored units are kept as they were enabled, so
+/// drop-ins are read whenever units are loaded.
+#[cfg(all(feature = "units", feature = "serde"))]
+pub fn apply_dropins(loader: &mut Loader) -> Result<()> {
+    let mut patched = baked_units()
+        .iter()
+        .filter_map(|u| warn_err(dropin::patch(u)))
+        .collect::<Vec<_>>();
+
+    // stored units are replaced even without drop-ins so removed ones no longer apply
+    patched.extend(
+        loader
+            .database()
+            .units
+            .values()
+            .map(|u| wrap_unit(warn_err(dropin::apply(u)).unwrap_or_else(|| u.clone()))),
+    );
+
+    if !patched.is_empty() {
+        loader.replace_units(patched)?;
+    }
+
     Ok(())
 }
@@ -2,12 +2,55 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use kanit_common::error::{Result, StaticError, WithError};
+use log::warn;
+
+use kanit_common::error::{Result, WithError};
 use kanit_unit::UnitInfo;
 
+#[derive(Clone, Copy, Debug)]
+enum EdgeKind {
+    Needs,
+    Wants,
+    After,
+    Before,
+}
+
+impl EdgeKind {
+    // edges point from a unit to what it waits on, so `before` reads as `after` from the other side
+    fn arrow(self) -> &'static str {
+        match self {
+            Self::Needs => "needs",
+            Self::Wants => "wants",
+            Self::After | Self::Before => "after",
+        }
+    }
+
+    // `before` and `after` only order units while `wants` also pulls the unit in
+    fn strength(self) -> u8 {
+        match self {
+            Self::After | Self::Before => 0,
+            Self::Wants => 1,
+            Self::Needs => 2,
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Self::Needs => "needs",
+            Self::Wants => "wants",
+            Self::After => "after",
+            Self::Before => "before",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Edge<T> {
     from: Rc<RefCell<Node<T>>>,
+    to: usize,
+    kind: EdgeKind,
+    // the unit which declared the dependency
+    declared_by: usize,
     deleted: bool,
 }
 
@@ -18,6 +61,66 @@ struct Node<T> {
     idx: usize,
 }
 
+type RcNode = Rc<RefCell<Node<UnitInfo>>>;
+type RcEdge = Rc<RefCell<Edge<UnitInfo>>>;
+
+// walks the dependencies left after sorting until a unit repeats
+fn find_cycle(nodes: &[RcNode], start: usize) -> Vec<RcEdge> {
+    let mut path = vec![];
+    let mut visited = vec![];
+    let mut current = start;
+
+    loop {
+        if let Some(pos) = visited.iter().position(|i| *i == current) {
+            return path.split_off(pos);
+        }
+
+        visited.push(current);
+
+        // unwrap: every remaining node waits on another remaining node
+        let edge = nodes[current]
+            .borrow()
+            .edges
+            .iter()
+            .find(|e| {
+                let e_b = e.borrow();
+                !e_b.deleted && e_b.from.borrow().idx == current
+            })
+            .unwrap()
+            .clone();
+
+        current = edge.borrow().to;
+        path.push(edge);
+    }
+}
+
+fn describe_cycle(nodes: &[RcNode], cycle: &[RcEdge]) -> String {
+    let name = |idx: usize| nodes[idx].borrow().data.name.clone();
+
+    let mut path = cycle
+        .first()
+        .map(|e| e.borrow().from.borrow().data.name.to_string())
+        .unwrap_or_default();
+    let mut origins = String::new();
+
+    for edge in cycle {
+        let edge_b = edge.borrow();
+
+        path.push_str(&format!(" -{}-> {}", edge_b.kind.arrow(), name(edge_b.to)));
+
+        let declared_by = nodes[edge_b.declared_by].borrow();
+
+        origins.push_str(&format!(
+            "\n  `{}` of `{}` ({})",
+            edge_b.kind.key(),
+            declared_by.data.name,
+            declared_by.data.source.as_deref().unwrap_or("built-in")
+        ));
+    }
+
+    format!("cyclic dependency detected: {}{}", path, origins)
+}
+
 pub fn obtain_load_order(units: Vec<UnitInfo>) -> Result<Vec<Vec<UnitInfo>>> {
     let mut nodes = vec![];
     let mut map = HashMap::new();
@@ -36,11 +139,15 @@ pub fn obtain_load_order(units: Vec<UnitInfo>) -> Result<Vec<Vec<UnitInfo>>> {
     for node in nodes.iter() {
         let mut node_b = node.borrow_mut();
         let dependencies = node_b.data.dependencies.clone();
+        let idx = node_b.idx;
 
         for dep in dependencies.needs.iter() {
             if let Some(unit) = map.get(&dep.clone()) {
                 let edge = Rc::new(RefCell::new(Edge {
                     from: node.clone(),
+                    to: unit.borrow().idx,
+                    kind: EdgeKind::Needs,
+                    declared_by: idx,
                     deleted: false,
                 }));
 
@@ -54,10 +161,18 @@ pub fn obtain_load_order(units: Vec<UnitInfo>) -> Result<Vec<Vec<UnitInfo>>> {
             }
         }
 
-        for dep in dependencies.wants.iter().chain(dependencies.after.iter()) {
+        for (dep, kind) in dependencies
+            .wants
+            .iter()
+            .map(|d| (d, EdgeKind::Wants))
+            .chain(dependencies.after.iter().map(|d| (d, EdgeKind::After)))
+        {
             if let Some(unit) = map.get(dep) {
                 let edge = Rc::new(RefCell::new(Edge {
                     from: node.clone(),
+                    to: unit.borrow().idx,
+                    kind,
+                    declared_by: idx,
                     deleted: false,
                 }));
 
@@ -72,6 +187,9 @@ pub fn obtain_load_order(units: Vec<UnitInfo>) -> Result<Vec<Vec<UnitInfo>>> {
 
                 let edge = Rc::new(RefCell::new(Edge {
                     from: unit.clone(),
+                    to: idx,
+                    kind: EdgeKind::Before,
+                    declared_by: idx,
                     deleted: false,
                 }));
 
@@ -81,6 +199,7 @@ pub fn obtain_load_order(units: Vec<UnitInfo>) -> Result<Vec<Vec<UnitInfo>>> {
         }
     }
 
+    let all = nodes.clone();
     let mut order = vec![];
 
     while !nodes.is_empty() {
@@ -115,10 +234,33 @@ pub fn obtain_load_order(units: Vec<UnitInfo>) -> Result<Vec<Vec<UnitInfo>>> {
             }
         }
 
-        order.push(round);
+        if starting_amount != nodes.len() {
+            order.push(round);
+            continue;
+        }
+
+        let cycle = find_cycle(&all, nodes[0].borrow().idx);
+        let msg = describe_cycle(&all, &cycle);
 
-        if starting_amount == nodes.len() {
-            Err(StaticError("cyclic dependency detected"))?;
+        // only a cycle of `needs` is impossible, otherwise the weakest edge is dropped
+        match cycle
+            .iter()
+            .filter(|e| e.borrow().kind.strength() < EdgeKind::Needs.strength())
+            .min_by_key(|e| e.borrow().kind.strength())
+        {
+            Some(edge) => {
+                let mut edge_b = edge.borrow_mut();
+
+                warn!(
+                    "ignoring `{}` of `{}` to break {}",
+                    edge_b.kind.key(),
+                    all[edge_b.declared_by].borrow().data.name,
+                    msg
+                );
+
+                edge_b.deleted = true;
+            }
+            None => Err(WithError::with(move || msg.clone()))?,
         }
     }
 
@@ -233,4 +375,40 @@ mod tests {
 
         assert!(obtain_load_order(to_unit_info(units)).is_err());
     }
+
+    #[test]
+    fn cyclic_path() {
+        let a = NullUnit("a", Dependencies::new().need(UnitName::from("b")).clone());
+        let b = NullUnit("b", Dependencies::new().need(UnitName::from("c")).clone());
+        let c = NullUnit("c", Dependencies::new().need(UnitName::from("a")).clone());
+
+        let units = vec![wrap_unit(a), wrap_unit(b), wrap_unit(c)];
+
+        let err = obtain_load_order(to_unit_info(units))
+            .unwrap_err()
+            .to_string();
+
+        assert!(["a -needs-> b", "b -needs-> c", "c -needs-> a"]
+            .iter()
+            .all(|e| err.contains(e)));
+    }
+
+    #[test]
+    fn soft_cycle() {
+        let a = NullUnit("a", Dependencies::new().need(UnitName::from("b")).clone());
+        let b = NullUnit("b", Dependencies::new().want(UnitName::from("c")).clone());
+        let c = NullUnit("c", Dependencies::new().after(UnitName::from("a")).clone());
+
+        let units = vec![wrap_unit(a), wrap_unit(b), wrap_unit(c)];
+
+        let order = obtain_load_order(to_unit_info(units)).unwrap();
+        let pos = |name: &str| {
+            order
+                .iter()
+                .position(|g| g.iter().any(|u| &*u.name == name))
+        };
+
+        // the `after` edge is the weakest so `c` starts first
+        assert!(pos("c") < pos("b") && pos("b") < pos("a"));
+    }
 }
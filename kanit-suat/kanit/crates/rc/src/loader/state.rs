@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kanit_control::{ActiveState, EventKind, UnitStatus};
+use kanit_unit::{Unit, UnitName};
+
+static OBSERVER: OnceLock<fn(EventKind)> = OnceLock::new();
+
+/// Registers a function called on every state transition, such as to publish them to monitors.
+pub fn observe(observer: fn(EventKind)) {
+    // ignore error since only one observer is needed
+    let _ = OBSERVER.set(observer);
+}
+
+/// What a unit is doing at runtime.
+#[derive(Debug, Clone)]
+pub struct RuntimeState {
+    pub state: ActiveState,
+    /// When the unit entered its state.
+    pub since: SystemTime,
+    pub pid: Option<u32>,
+    /// Why the unit failed or was skipped.
+    pub error: Option<String>,
+    /// The exit code of the unit's process, or 128 plus the signal which killed it.
+    pub exit_status: Option<i32>,
+}
+
+impl Default for RuntimeState {
+    fn default() -> Self {
+        Self {
+            state: ActiveState::Inactive,
+            since: SystemTime::now(),
+            pid: None,
+            error: None,
+            exit_status: None,
+        }
+    }
+}
+
+impl RuntimeState {
+    pub fn to_status(&self, name: &UnitName, level: usize) -> UnitStatus {
+        UnitStatus {
+            name: name.to_string(),
+            level,
+            state: self.state,
+            since: self
+                .since
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            pid: self.pid,
+            error: self.error.clone(),
+            exit_status: self.exit_status,
+        }
+    }
+}
+
+/// Runtime states of every unit which has been acted on.
+/// Shared outside of the loader so units can be tracked while the loader is borrowed.
+#[derive(Debug, Default)]
+pub struct StateTable(RefCell<HashMap<UnitName, RuntimeState>>);
+
+impl StateTable {
+    pub fn get(&self, name: &UnitName) -> RuntimeState {
+        self.0.borrow().get(name).cloned().unwrap_or_default()
+    }
+
+    fn update<F: FnOnce(&mut RuntimeState)>(&self, name: UnitName, state: ActiveState, f: F) {
+        let mut states = self.0.borrow_mut();
+        let entry = states.entry(name.clone()).or_default();
+
+        entry.state = state;
+        entry.since = SystemTime::now();
+
+        f(entry);
+
+        let event = EventKind::State {
+            unit: name.to_string(),
+            state,
+            pid: entry.pid,
+            error: entry.error.clone(),
+            exit_status: entry.exit_status,
+        };
+
+        // the observer may look up states itself
+        drop(states);
+
+        if let Some(observer) = OBSERVER.get() {
+            observer(event);
+        }
+    }
+
+    pub fn activating(&self, name: UnitName) {
+        self.update(name, ActiveState::Activating, |s| {
+            s.error = None;
+            s.exit_status = None;
+        });
+    }
+
+    pub fn active(&self, unit: &dyn Unit) {
+        self.update(unit.name(), ActiveState::Active, |s| s.pid = unit.pid());
+    }
+
+    pub fn deactivating(&self, name: UnitName) {
+        self.update(name, ActiveState::Deactivating, |_| {});
+    }
+
+    pub fn inactive(&self, name: UnitName) {
+        self.update(name, ActiveState::Inactive, |s| s.pid = None);
+    }
+
+    pub fn skipped<S: ToString>(&self, name: UnitName, reason: S) {
+        self.update(name, ActiveState::Skipped, |s| {
+            s.pid = None;
+            s.error = Some(reason.to_string());
+        });
+    }
+
+    pub fn failed<S: ToString>(&self, name: UnitName, error: S) {
+        self.update(name, ActiveState::Failed, |s| {
+            s.pid = None;
+            s.error = Some(error.to_string());
+        });
+    }
+
+    /// Units whose process is expected to be running.
+    pub fn running(&self) -> Vec<(UnitName, u32)> {
+        self.0
+            .borrow()
+            .iter()
+            .filter(|(_, s)| s.state == ActiveState::Active)
+            .filter_map(|(n, s)| s.pid.map(|p| (n.clone(), p)))
+            .collect()
+    }
+
+    /// Records the exit of a unit's process, units exiting successfully become inactive.
+    pub fn exited(&self, name: UnitName, exit_status: i32) {
+        let state = if exit_status == 0 {
+            ActiveState::Inactive
+        } else {
+            ActiveState::Failed
+        };
+
+        self.update(name, state, |s| {
+            s.pid = None;
+            s.exit_status = Some(exit_status);
+
+            if exit_status != 0 {
+                s.error = Some(format!("exited with status {}", exit_status));
+            }
+        });
+    }
+}
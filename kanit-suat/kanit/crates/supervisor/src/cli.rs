@@ -1,68 +1,453 @@
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsFd, BorrowedFd, FromRawFd};
 use std::os::unix::prelude::ExitStatusExt;
 use std::process::{ExitCode, ExitStatus};
+use std::time::{Duration, Instant};
 
 use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::prctl::set_child_subreaper;
 use nix::sys::signal;
-use nix::sys::signal::Signal;
+use nix::sys::signal::{kill, Signal};
 use nix::sys::signalfd::{SfdFlags, SigSet, SignalFd};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
 
-use crate::{spawn, spawn_restart, Supervisor};
+use crate::tree::{descendants, kill_tree};
+use crate::{
+    next_restart, read_pid_file, spawn, Restart, Restarts, ServiceType, Supervisor, UnitState,
+    STOP_TIMEOUT,
+};
+
+fn exited(status: WaitStatus) -> Option<(Pid, ExitStatus)> {
+    match status {
+        WaitStatus::Exited(pid, code) => Some((pid, ExitStatus::from_raw(code << 8))),
+        WaitStatus::Signaled(pid, sig, _) => Some((pid, ExitStatus::from_raw(sig as i32))),
+        _ => None,
+    }
+}
+
+// reaps every exited child, returning the status of `main` if it exited
+fn reap(main: Option<Pid>) -> nix::Result<Option<ExitStatus>> {
+    let mut status = None;
+
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return Ok(status),
+            Err(e) => return Err(e),
+            Ok(s) => {
+                if let Some((pid, s)) = exited(s) {
+                    if Some(pid) == main {
+                        status = Some(s);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// tells whoever spawned the supervisor whether the process started, only the first report is sent
+struct Notify(Option<File>);
+
+impl Notify {
+    fn new(fd: Option<i32>) -> Self {
+        // processes spawned later mustn't hold the pipe open
+        Self(fd.and_then(
+            |fd| match fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)) {
+                // SAFETY: the fd is open and passed to the supervisor for this alone
+                Ok(_) => Some(unsafe { File::from_raw_fd(fd) }),
+                Err(e) => {
+                    eprintln!("failed to use notify fd {}: {}", fd, e);
+                    None
+                }
+            },
+        ))
+    }
+
+    fn send(&mut self, started: Result<(), String>) {
+        if let Some(mut file) = self.0.take() {
+            let message = started.map_or_else(|e| e, |_| "ok".to_string());
+
+            if let Err(e) = file.write_all(message.as_bytes()) {
+                eprintln!("failed to notify: {}", e);
+            }
+        }
+    }
+}
+
+fn save_state(name: Option<&str>, state: &UnitState) {
+    if let Some(name) = name {
+        if let Err(e) = state.write(name) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+// waits for the timeout to pass or `until` to be received
+// returns `false` if the supervisor was told to stop while waiting
+fn wait_signal(
+    sfd: &mut SignalFd,
+    timeout: Option<Duration>,
+    until: Option<Signal>,
+) -> nix::Result<bool> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX)
+            }
+            None => PollTimeout::NONE,
+        };
+
+        if poll(&mut [PollFd::new(sfd.as_fd(), PollFlags::POLLIN)], timeout)? == 0 {
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Ok(true);
+            }
+
+            continue;
+        }
+
+        if let Some(sig) = sfd.read_signal()? {
+            match Signal::try_from(sig.ssi_signo as i32) {
+                Ok(Signal::SIGTERM) => return Ok(false),
+                Ok(Signal::SIGCHLD) => {
+                    reap(None)?;
+                }
+                Ok(sig) if Some(sig) == until => return Ok(true),
+                _ => {}
+            }
+        }
+    }
+}
+
+// asks the tree to stop, killing whatever is left once the timeout passes
+// returns the status of `main` if it exited
+fn stop_tree(sfd: &mut SignalFd, supervisor: Pid, main: Option<Pid>) -> Option<ExitStatus> {
+    let deadline = Instant::now() + Duration::from_secs(STOP_TIMEOUT);
+    let mut status = None;
+
+    // the whole tree is signalled as a forking service may not have written its PID file yet,
+    // leaving the daemon unknown
+    kill_tree(supervisor, Signal::SIGTERM);
+
+    while !descendants(supervisor).is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timeout = PollTimeout::try_from(remaining).unwrap_or(PollTimeout::MAX);
+
+        match poll(&mut [PollFd::new(sfd.as_fd(), PollFlags::POLLIN)], timeout) {
+            Ok(0) if remaining.is_zero() => {
+                eprintln!("process didn't stop in time, killing it");
+                break;
+            }
+            // the timeout is rounded to milliseconds
+            Ok(0) => continue,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("failed to poll: {}", e);
+                break;
+            }
+        }
+
+        if let Ok(Some(sig)) = sfd.read_signal() {
+            if sig.ssi_signo as i32 == Signal::SIGCHLD as i32 {
+                if let Ok(Some(s)) = reap(main) {
+                    status = Some(s);
+                }
+            }
+        }
+    }
+
+    kill_leftovers(supervisor, main).or(status)
+}
+
+// kills every remaining descendant and waits for them to be gone
+// returns the status of `main` if it exited
+fn kill_leftovers(supervisor: Pid, main: Option<Pid>) -> Option<ExitStatus> {
+    let mut status = None;
+
+    loop {
+        let leftovers = descendants(supervisor);
+
+        if leftovers.is_empty() {
+            return status;
+        }
+
+        // orphans of killed processes are reparented to the supervisor, so the tree is walked again
+        for pid in leftovers {
+            let _ = kill(pid, Signal::SIGKILL);
+        }
+
+        match waitpid(None, None) {
+            Ok(s) => {
+                if let Some((pid, s)) = exited(s) {
+                    if Some(pid) == main {
+                        status = Some(s);
+                    }
+                }
+            }
+            Err(Errno::ECHILD) => return status,
+            Err(_) => {}
+        }
+    }
+}
+
+// returns `false` if the supervisor was told to stop while waiting
+fn wait_for_connection(sfd: &mut SignalFd, fds: usize) -> nix::Result<bool> {
+    loop {
+        let mut poll_fds = (3..3 + fds as i32)
+            // SAFETY: init passes the sockets from fd 3 onwards which stay open
+            .map(|fd| PollFd::new(unsafe { BorrowedFd::borrow_raw(fd) }, PollFlags::POLLIN))
+            .collect::<Vec<_>>();
+
+        poll_fds.push(PollFd::new(sfd.as_fd(), PollFlags::POLLIN));
+
+        poll(&mut poll_fds, PollTimeout::NONE)?;
+
+        // unwrap: the signal fd was pushed last
+        let signalled = poll_fds
+            .last()
+            .unwrap()
+            .revents()
+            .is_some_and(|r| r.contains(PollFlags::POLLIN));
+
+        if !signalled {
+            return Ok(true);
+        }
+
+        if let Some(sig) = sfd.read_signal()? {
+            if sig.ssi_signo as i32 == Signal::SIGTERM as i32 {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+// supervises the process until it shouldn't be restarted anymore or the supervisor is stopped
+fn supervise(
+    cfg: &mut Supervisor,
+    sfd: &mut SignalFd,
+    supervisor: Pid,
+    notify: &mut Notify,
+) -> ExitCode {
+    let forking = cfg.service_type == Some(ServiceType::Forking);
+
+    let mut restarts = Restarts::new(cfg);
+
+    // the process being supervised, for forking services this becomes the forked PID
+    let mut main = match spawn(cfg) {
+        Ok(child) => Pid::from_raw(child.id() as i32),
+        Err(e) => {
+            eprintln!("{}", e);
+            notify.send(Err(e.to_string()));
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut forked = false;
+
+    // forking services have started once their PID file is read
+    if !forking {
+        notify.send(Ok(()));
+    }
+
+    restarts.started();
+
+    // the exit history and restart count are kept across starts, unlike what refers to a run
+    let mut state = cfg
+        .name
+        .as_deref()
+        .and_then(|name| UnitState::read(name).ok())
+        .unwrap_or_default();
+    state.stopped();
+    state.failed = false;
+    state.supervisor = Some(supervisor.as_raw() as u32);
+    state.spawned(main.as_raw() as u32);
+    save_state(cfg.name.as_deref(), &state);
+
+    loop {
+        match sfd.read_signal() {
+            Ok(Some(sig)) => match Signal::try_from(sig.ssi_signo as i32) {
+                Ok(Signal::SIGCHLD) => {
+                    let mut status = match reap(Some(main)) {
+                        Ok(Some(status)) => status,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            eprintln!("failed to waitpid: {}", e);
+                            return ExitCode::FAILURE;
+                        }
+                    };
+
+                    if forking && !forked && status.success() {
+                        // unwrap: checked on startup
+                        match read_pid_file(cfg.pid_file.as_ref().unwrap()) {
+                            // the service may have already exited and been reaped above, while
+                            // a stale PID file may point to an unrelated process
+                            Ok(pid) if descendants(supervisor).contains(&pid) => {
+                                main = pid;
+                                forked = true;
+                                state.pid = Some(pid.as_raw() as u32);
+                                save_state(cfg.name.as_deref(), &state);
+                                notify.send(Ok(()));
+                                continue;
+                            }
+                            Ok(pid) => {
+                                let e = format!("forked service {} isn't running", pid);
+
+                                eprintln!("{}", e);
+                                notify.send(Err(e));
+                            }
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                notify.send(Err(e.to_string()));
+                            }
+                        }
+
+                        status = ExitStatus::from_raw(1 << 8);
+                    }
+
+                    state.exited(status);
+                    save_state(cfg.name.as_deref(), &state);
+
+                    // only a forking service can exit before it has started
+                    notify.send(Err(format!("process exited before starting ({})", status)));
+
+                    let waited = match next_restart(cfg, &mut restarts, status) {
+                        Restart::Never => return ExitCode::SUCCESS,
+                        Restart::After(delay) => {
+                            state.restarts += 1;
+                            wait_signal(sfd, Some(delay), None)
+                        }
+                        Restart::Failed => {
+                            eprintln!("process is restarting too often, marking as failed");
+
+                            state.failed = true;
+                            save_state(cfg.name.as_deref(), &state);
+
+                            let reset = wait_signal(sfd, None, Some(Signal::SIGUSR1));
+
+                            state.failed = false;
+                            restarts.reset();
+
+                            reset
+                        }
+                    };
+
+                    match waited {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            // children of the exited process may still be running
+                            stop_tree(sfd, supervisor, None);
+                            return ExitCode::SUCCESS;
+                        }
+                        Err(e) => {
+                            eprintln!("failed to wait: {}", e);
+                            return ExitCode::FAILURE;
+                        }
+                    }
+
+                    // a new instance could clash with what the previous one left behind
+                    kill_leftovers(supervisor, None);
+
+                    main = match spawn(cfg) {
+                        Ok(child) => Pid::from_raw(child.id() as i32),
+                        Err(e) => {
+                            eprintln!("failed to restart process: {}", e);
+
+                            state.failed = true;
+                            save_state(cfg.name.as_deref(), &state);
+
+                            return ExitCode::FAILURE;
+                        }
+                    };
+                    forked = false;
+
+                    restarts.started();
+
+                    state.spawned(main.as_raw() as u32);
+                    save_state(cfg.name.as_deref(), &state);
+                }
+                Ok(Signal::SIGTERM) => {
+                    if let Some(status) = stop_tree(sfd, supervisor, Some(main)) {
+                        state.exited(status);
+                        save_state(cfg.name.as_deref(), &state);
+                    }
+
+                    return ExitCode::SUCCESS;
+                }
+                _ => {}
+            },
+            Ok(None) => unreachable!(),
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+}
 
 pub fn handle_cli() -> ExitCode {
     match Supervisor::from_env() {
         Ok(mut cfg) => {
+            let mut notify = Notify::new(cfg.notify_fd);
+
+            if cfg.service_type == Some(ServiceType::Forking) && cfg.pid_file.is_none() {
+                eprintln!("forking services require a PID file");
+                notify.send(Err("forking services require a PID file".to_string()));
+                return ExitCode::FAILURE;
+            }
+
+            // orphaned descendants get reparented to us instead of init so they can be tracked
+            if let Err(e) = set_child_subreaper(true) {
+                let e = format!("failed to become subreaper: {}", e);
+
+                eprintln!("{}", e);
+                notify.send(Err(e));
+                return ExitCode::FAILURE;
+            }
+
+            let supervisor = Pid::this();
+
             let mut mask = SigSet::empty();
             mask.add(signal::SIGCHLD);
             mask.add(signal::SIGTERM);
+            // resets a failed process
+            mask.add(signal::SIGUSR1);
             mask.thread_block().unwrap();
 
-            let mut sfd = SignalFd::with_flags(&mask, SfdFlags::empty()).unwrap();
-
-            let mut child = spawn(&cfg).expect("spawn child");
-
-            loop {
-                match sfd.read_signal() {
-                    Ok(Some(sig)) => match Signal::try_from(sig.ssi_signo as i32) {
-                        Ok(Signal::SIGCHLD) => {
-                            loop {
-                                let pid = waitpid(None, Some(WaitPidFlag::WNOHANG));
-
-                                match pid {
-                                    Ok(WaitStatus::StillAlive) => break,
-                                    Err(Errno::ECHILD) => break,
-                                    Err(e) => {
-                                        eprintln!("failed to waitpid: {}", e);
-                                        return ExitCode::FAILURE;
-                                    }
-                                    _ => {}
-                                }
-                            }
+            let mut sfd = SignalFd::with_flags(&mask, SfdFlags::SFD_CLOEXEC).unwrap();
 
-                            if let Some(c) =
-                                spawn_restart(&mut cfg, ExitStatus::from_raw(sig.ssi_status), true)
-                                    .expect("restart child")
-                            {
-                                child = c;
-                            } else {
-                                return ExitCode::SUCCESS;
-                            }
-                        }
-                        Ok(Signal::SIGTERM) => {
-                            child.kill().expect("kill child");
-                            if let Some(attempts) = cfg.restart_attempts {
-                                cfg.restart_attempts = Some(attempts + 1);
-                            }
-                        }
-                        _ => {}
-                    },
-                    Ok(None) => unreachable!(),
-                    Err(err) => {
-                        eprintln!("{}", err);
+            if let (true, Some(fds)) = (cfg.lazy, cfg.listen_fds) {
+                // the unit is up once its sockets are listened on
+                notify.send(Ok(()));
+
+                match wait_for_connection(&mut sfd, fds) {
+                    Ok(true) => {}
+                    Ok(false) => return ExitCode::SUCCESS,
+                    Err(e) => {
+                        eprintln!("failed to wait for connection: {}", e);
                         return ExitCode::FAILURE;
                     }
                 }
             }
+
+            let code = supervise(&mut cfg, &mut sfd, supervisor, &mut notify);
+
+            // nothing outlives the supervisor, such as a daemon whose PID file was never read or
+            // children left behind by a process which won't be restarted
+            kill_leftovers(supervisor, None);
+
+            // the exit history is kept, but the state no longer refers to running processes
+            if let Some(name) = cfg.name.as_deref() {
+                if let Ok(mut state) = UnitState::read(name) {
+                    state.stopped();
+                    save_state(Some(name), &state);
+                }
+            }
+
+            code
         }
         Err(e) => {
             eprintln!("{}", e);
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result};
+
+use crate::flags::Supervisor;
+
+pub type EnvPairs = Vec<(String, String)>;
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return value[1..value.len() - 1].to_string();
+    }
+
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut unquoted = String::new();
+        let mut chars = value[1..value.len() - 1].chars();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some(n @ ('"' | '\\' | '$' | '`'))) => {
+                    unquoted.push(n);
+                    chars.next();
+                }
+                _ => unquoted.push(c),
+            }
+        }
+
+        return unquoted;
+    }
+
+    value.to_string()
+}
+
+/// Parses a shell-style environment file of `KEY=value` lines.
+///
+/// Comments, blank lines, `export` prefixes, and quoted values are understood so OpenRC `conf.d`
+/// files can be loaded as is. Lines which aren't assignments are ignored.
+pub fn parse_env_file(contents: &str) -> EnvPairs {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+
+            let valid = key
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+            valid.then(|| (key.to_string(), unquote(value.trim())))
+        })
+        .collect()
+}
+
+/// Loads an environment file, paths prefixed with `-` are allowed to not exist.
+pub fn load_env_file(path: &str) -> Result<EnvPairs> {
+    let (path, optional) = match path.strip_prefix('-') {
+        Some(path) => (path, true),
+        None => (path, false),
+    };
+
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(parse_env_file(&contents)),
+        Err(e) if optional && e.kind() == ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => {
+            let path = path.to_string();
+
+            Err(e).with_context(move || format!("failed to read environment file `{}`", path))
+        }
+    }
+}
+
+/// Expands `${VAR}` references, unset variables expand to nothing.
+pub fn expand(s: &str, vars: &HashMap<String, String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+
+        expanded.push_str(&rest[..start]);
+
+        if let Some(value) = vars.get(&rest[start + 2..start + end]) {
+            expanded.push_str(value);
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    expanded.push_str(rest);
+
+    expanded
+}
+
+/// The environment of the supervised process, in order of increasing precedence: the global
+/// environment file, the unit's environment files, then its explicit variables.
+///
+/// Returns the variables set for the process and every variable visible for expansion.
+pub fn environment(cfg: &Supervisor) -> Result<(EnvPairs, HashMap<String, String>)> {
+    let mut vars = env::vars().collect::<HashMap<_, _>>();
+    let mut pairs = vec![];
+
+    let global = format!("-{}", constants::KAN_ENVIRONMENT);
+
+    for file in [&global].into_iter().chain(cfg.environment_file.iter()) {
+        pairs.extend(load_env_file(file)?);
+    }
+
+    pairs.extend(
+        cfg.env
+            .iter()
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+    );
+
+    // later variables may reference earlier ones
+    for (key, value) in pairs.iter_mut() {
+        *value = expand(value, &vars);
+        vars.insert(key.clone(), value.clone());
+    }
+
+    Ok((pairs, vars))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_conf_d() {
+        let parsed = parse_env_file(
+            "# comment\n\nexport FOO=bar\nBAZ=\"a \\\"b\\\"\"\nQUX='${FOO}'\nnot an assignment\n1A=b",
+        );
+
+        assert_eq!(
+            parsed,
+            vec![
+                ("FOO".to_string(), "bar".to_string()),
+                ("BAZ".to_string(), "a \"b\"".to_string()),
+                ("QUX".to_string(), "${FOO}".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_vars() {
+        let vars = HashMap::from([("FOO".to_string(), "bar".to_string())]);
+
+        assert_eq!(expand("/run/${FOO}/${MISSING}x", &vars), "/run/bar/x");
+        assert_eq!(expand("${FOO", &vars), "${FOO");
+    }
+}
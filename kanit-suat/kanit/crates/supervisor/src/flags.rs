@@ -56,23 +56,122 @@ impl fmt::Display for RestartPolicy {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceType {
+    /// The spawned process is the service.
+    Simple,
+    /// The spawned process forks the service and exits, leaving its PID in a PID file.
+    Forking,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseServiceTypeError;
+
+impl fmt::Display for ParseServiceTypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "expected `simple` or `forking`")
+    }
+}
+
+impl FromStr for ServiceType {
+    type Err = ParseServiceTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "simple" => Ok(Self::Simple),
+            "forking" => Ok(Self::Forking),
+            _ => Err(ParseServiceTypeError),
+        }
+    }
+}
+
+impl fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Simple => write!(f, "simple"),
+            Self::Forking => write!(f, "forking"),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[cfg_attr(feature = "rkyv", derive(Archive, rkyv::Serialize, rkyv::Deserialize))]
+#[cfg_attr(feature = "rkyv", archive(check_bytes))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdinMode {
+    /// Stdin reads from `/dev/null`.
+    Null,
+    /// Stdin, stdout and stderr are attached to the tty, which becomes the controlling terminal.
+    Tty,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseStdinModeError;
+
+impl fmt::Display for ParseStdinModeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "expected `null` or `tty`")
+    }
+}
+
+impl FromStr for StdinMode {
+    type Err = ParseStdinModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "null" => Ok(Self::Null),
+            "tty" => Ok(Self::Tty),
+            _ => Err(ParseStdinModeError),
+        }
+    }
+}
+
+impl fmt::Display for StdinMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Tty => write!(f, "tty"),
+        }
+    }
+}
+
 #[cfg(feature = "cli")]
 xflags::xflags! {
     src "./src/flags.rs"
 
     cmd supervisor {
+        /// Name of the unit, used to record its state.
+        optional -n, --name name: String
         /// Delay used before restarting an exited process.
         optional -d, --restart-delay delay: u64
+        /// Maximum delay when backing off restarts, enables exponential backoff starting from the
+        /// restart delay, or 1 second if unset.
+        optional --restart-max-delay delay: u64
         /// Amount of times to restart a process before giving up.
         optional -a, --restart-attempts attempts: u64
         /// Policy to use before restarting, either `never`, `always`, `on-success`, or `on-failure`.
         optional -P, --restart-policy policy: RestartPolicy
+        /// Amount of starts allowed within the start limit interval before failing.
+        optional --start-limit-burst starts: u64
+        /// Interval in seconds used for the start limit, defaults to 10.
+        optional --start-limit-interval interval: u64
+        /// Type of the service, either `simple` or `forking`.
+        optional -t, --service-type type: ServiceType
+        /// Path to the PID file written by a forking service.
+        optional --pid-file path: String
         /// Set the working directory.
         optional -p, --pwd pwd: String
         /// Set the root directory.
         optional -r, --root root: String
         /// Set an environment variable (NAME=VAR).
         repeated -e, --env pair: String
+        /// Load environment variables from a file, prefix with `-` to allow it to not exist.
+        repeated --environment-file path: String
         /// Set the process group.
         optional -g, --group gid: String
         /// Set the process user.
@@ -81,6 +180,20 @@ xflags::xflags! {
         optional --stdout path: String
         /// Redirect stderr to path.
         optional --stderr path: String
+        /// Set stdin, either `null` or `tty`.
+        optional --stdin mode: StdinMode
+        /// Path to the tty used when stdin is `tty`.
+        optional --tty-path path: String
+        /// Reset the tty before starting the process.
+        optional --tty-reset
+        /// Run the process in a new session.
+        optional --setsid
+        /// Amount of listening sockets passed from fd 3 onwards.
+        optional --listen-fds count: usize
+        /// Wait for a connection on a passed socket before starting the process.
+        optional --lazy
+        /// Write `ok` to this fd once the process has started, or why it failed to start.
+        optional --notify-fd fd: i32
         /// Command to execute.
         required cmd: String
         /// Arguments passed to the command.
@@ -104,16 +217,38 @@ pub struct Supervisor {
     pub cmd: String,
     pub args: Vec<String>,
 
+    // units already have a name
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub name: Option<String>,
     pub restart_delay: Option<u64>,
+    pub restart_max_delay: Option<u64>,
     pub restart_attempts: Option<u64>,
     pub restart_policy: Option<RestartPolicy>,
+    pub start_limit_burst: Option<u64>,
+    pub start_limit_interval: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub service_type: Option<ServiceType>,
+    pub pid_file: Option<String>,
     pub pwd: Option<String>,
     pub root: Option<String>,
     pub env: Vec<String>,
+    pub environment_file: Vec<String>,
     pub group: Option<String>,
     pub user: Option<String>,
     pub stdout: Option<String>,
     pub stderr: Option<String>,
+    pub stdin: Option<StdinMode>,
+    pub tty_path: Option<String>,
+    pub tty_reset: bool,
+    pub setsid: bool,
+    // only known once init has bound the unit's sockets
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub listen_fds: Option<usize>,
+    pub lazy: bool,
+    // only known once init has created the pipe
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    pub notify_fd: Option<i32>,
 }
 
 #[cfg(feature = "cli")]
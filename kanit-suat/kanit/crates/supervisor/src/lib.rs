@@ -1,9 +1,15 @@
 #[cfg(feature = "cli")]
 pub use cli::handle_cli;
+pub use environment::*;
 pub use flags::*;
+pub use state::*;
 pub use supervisor::*;
 
 #[cfg(feature = "cli")]
 mod cli;
+mod environment;
 mod flags;
+mod state;
 mod supervisor;
+#[cfg(feature = "cli")]
+mod tree;
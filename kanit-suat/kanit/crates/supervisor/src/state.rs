@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use kanit_common::constants;
+use kanit_common::error::{Context, Result, StaticError};
+
+/// Amount of exits kept in the history.
+pub const EXIT_HISTORY: usize = 10;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Code(i32),
+    Signal(i32),
+}
+
+impl From<ExitStatus> for Exit {
+    fn from(status: ExitStatus) -> Self {
+        match (status.code(), status.signal()) {
+            (Some(code), _) => Self::Code(code),
+            (None, Some(sig)) => Self::Signal(sig),
+            (None, None) => Self::Code(-1),
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "code {}", code),
+            Self::Signal(sig) => write!(f, "signal {}", sig),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExitRecord {
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    pub exit: Exit,
+}
+
+/// Runtime state of a supervised process, stored at `/run/kanit/units/<name>.state`.
+///
+/// The file is made of `key value` lines:
+/// ```text
+/// supervisor 1230
+/// pid 1234
+/// started 1712345678
+/// restarts 2
+/// exit 1712345600 code 1
+/// exit 1712345650 signal 9
+/// ```
+/// `pid` is omitted when the process isn't running, `failed` is present once the process has hit
+/// its start limit, and exits are ordered oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct UnitState {
+    pub supervisor: Option<u32>,
+    pub pid: Option<u32>,
+    pub failed: bool,
+    /// Seconds since the unix epoch.
+    pub started: u64,
+    pub restarts: u64,
+    pub exits: VecDeque<ExitRecord>,
+}
+
+impl UnitState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn path(name: &str) -> PathBuf {
+        PathBuf::from(constants::KAN_UNIT_STATE_DIR).join(format!("{}.state", name))
+    }
+
+    pub fn spawned(&mut self, pid: u32) {
+        self.pid = Some(pid);
+        self.started = now();
+    }
+
+    pub fn exited(&mut self, status: ExitStatus) {
+        self.pid = None;
+
+        if self.exits.len() == EXIT_HISTORY {
+            self.exits.pop_front();
+        }
+
+        self.exits.push_back(ExitRecord {
+            time: now(),
+            exit: status.into(),
+        });
+    }
+
+    /// Clears the processes once the supervisor exits, the exit history is kept.
+    pub fn stopped(&mut self) {
+        self.supervisor = None;
+        self.pid = None;
+    }
+
+    /// The supervisor if it's still running. A supervisor which was killed never clears its state,
+    /// so the PID is only trusted while it still belongs to a supervisor.
+    pub fn running_supervisor(&self) -> Option<u32> {
+        let pid = self.supervisor?;
+        let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        let arg0 = cmdline.split(|b| *b == 0).next()?;
+
+        (Path::new(OsStr::from_bytes(arg0)).file_name()? == "kanit-supervisor").then_some(pid)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut state = Self::new();
+
+        for line in contents.lines() {
+            let mut parts = line.split_whitespace();
+
+            let num = |part: Option<&str>| -> Result<i64> {
+                part.context("expected value")?
+                    .parse::<i64>()
+                    .context("failed to parse value")
+            };
+
+            match parts.next() {
+                Some("supervisor") => state.supervisor = Some(num(parts.next())? as u32),
+                Some("pid") => state.pid = Some(num(parts.next())? as u32),
+                Some("failed") => state.failed = true,
+                Some("started") => state.started = num(parts.next())? as u64,
+                Some("restarts") => state.restarts = num(parts.next())? as u64,
+                Some("exit") => {
+                    let time = num(parts.next())? as u64;
+
+                    let exit = match parts.next() {
+                        Some("code") => Exit::Code(num(parts.next())? as i32),
+                        Some("signal") => Exit::Signal(num(parts.next())? as i32),
+                        _ => Err(StaticError("expected `code` or `signal`"))?,
+                    };
+
+                    state.exits.push_back(ExitRecord { time, exit });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(state)
+    }
+
+    pub fn read(name: &str) -> Result<Self> {
+        let name_c = name.to_string();
+
+        let contents = fs::read_to_string(Self::path(name))
+            .with_context(move || format!("failed to read state of `{}`", name_c))?;
+
+        Self::parse(&contents)
+    }
+
+    /// Atomically replaces the state file.
+    pub fn write(&self, name: &str) -> Result<()> {
+        fs::create_dir_all(constants::KAN_UNIT_STATE_DIR)
+            .context("failed to create state directory")?;
+
+        let path = Self::path(name);
+        let tmp = path.with_extension("state.tmp");
+
+        fs::write(&tmp, self.to_string()).context("failed to write state")?;
+        fs::rename(&tmp, &path).context("failed to replace state")?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for UnitState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(supervisor) = self.supervisor {
+            writeln!(f, "supervisor {}", supervisor)?;
+        }
+
+        if let Some(pid) = self.pid {
+            writeln!(f, "pid {}", pid)?;
+        }
+
+        if self.failed {
+            writeln!(f, "failed")?;
+        }
+
+        writeln!(f, "started {}", self.started)?;
+        writeln!(f, "restarts {}", self.restarts)?;
+
+        for record in self.exits.iter() {
+            writeln!(f, "exit {} {}", record.time, record.exit)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut state = UnitState::new();
+
+        state.supervisor = Some(1230);
+        state.spawned(1234);
+        state.failed = true;
+        state.restarts = 2;
+        state.exits.push_back(ExitRecord {
+            time: 1712345600,
+            exit: Exit::Code(1),
+        });
+        state.exits.push_back(ExitRecord {
+            time: 1712345650,
+            exit: Exit::Signal(9),
+        });
+
+        let parsed = UnitState::parse(&state.to_string()).unwrap();
+
+        assert_eq!(parsed.supervisor, Some(1230));
+        assert_eq!(parsed.pid, Some(1234));
+        assert!(parsed.failed);
+        assert_eq!(parsed.started, state.started);
+        assert_eq!(parsed.restarts, 2);
+        assert_eq!(
+            parsed
+                .exits
+                .iter()
+                .map(|e| (e.time, e.exit))
+                .collect::<Vec<_>>(),
+            [(1712345600, Exit::Code(1)), (1712345650, Exit::Signal(9))]
+        );
+        assert_eq!(parsed.to_string(), state.to_string());
+    }
+
+    #[test]
+    fn stopped_round_trip() {
+        let mut state = UnitState::new();
+
+        state.supervisor = Some(1230);
+        state.spawned(1234);
+        state.stopped();
+
+        let parsed = UnitState::parse(&state.to_string()).unwrap();
+
+        assert_eq!(parsed.supervisor, None);
+        assert_eq!(parsed.pid, None);
+        assert_eq!(parsed.running_supervisor(), None);
+    }
+}
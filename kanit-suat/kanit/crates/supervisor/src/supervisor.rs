@@ -1,53 +1,303 @@
-use std::fs::OpenOptions;
-use std::os::unix::fs::chroot;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{chroot, OpenOptionsExt};
 use std::os::unix::process::CommandExt;
 use std::process::{Child, Command, ExitStatus, Stdio};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use std::{fs, process, ptr};
 
-use nix::unistd::{Group, User};
+use nix::fcntl::OFlag;
+use nix::libc;
+use nix::sys::signal::SigSet;
+use nix::sys::termios::{
+    tcflush, tcgetattr, tcsetattr, ControlFlags, FlushArg, InputFlags, LocalFlags, OutputFlags,
+    SetArg, SpecialCharacterIndices,
+};
+use nix::unistd::{setsid, Group, Pid, User};
 
 use kanit_common::error::{Context, Result};
 
-use crate::flags::{RestartPolicy, Supervisor};
+use crate::environment::{environment, expand};
+use crate::flags::{RestartPolicy, StdinMode, Supervisor};
 
-pub fn spawn_restart(
-    cfg: &mut Supervisor,
-    status: ExitStatus,
-    delay: bool,
-) -> Result<Option<Child>> {
+/// Interval used for the start limit if none is set.
+pub const DEFAULT_START_LIMIT_INTERVAL: u64 = 10;
+
+/// Delay backoff starts from if no restart delay is set.
+pub const DEFAULT_BACKOFF_DELAY: u64 = 1;
+
+/// Seconds a stopping process is given to exit before it's killed.
+pub const STOP_TIMEOUT: u64 = 10;
+
+pub enum Restart {
+    /// The process shouldn't be restarted.
+    Never,
+    /// The process is restarting too often and should be considered failed.
+    Failed,
+    /// The process should be restarted after a delay.
+    After(Duration),
+}
+
+/// Tracks the starts of a process to back off and rate limit restarts.
+pub struct Restarts {
+    starts: VecDeque<Instant>,
+    burst: usize,
+    failures: u32,
+}
+
+impl Restarts {
+    pub fn new(cfg: &Supervisor) -> Self {
+        Self {
+            starts: VecDeque::new(),
+            burst: cfg.start_limit_burst.unwrap_or(1).max(1) as usize,
+            failures: 0,
+        }
+    }
+
+    pub fn started(&mut self) {
+        if self.starts.len() == self.burst {
+            self.starts.pop_front();
+        }
+
+        self.starts.push_back(Instant::now());
+    }
+
+    pub fn reset(&mut self) {
+        self.starts.clear();
+        self.failures = 0;
+    }
+}
+
+pub fn next_restart(cfg: &mut Supervisor, restarts: &mut Restarts, status: ExitStatus) -> Restart {
     match cfg.restart_policy.unwrap_or(RestartPolicy::Never) {
-        RestartPolicy::Never => return Ok(None),
-        RestartPolicy::OnFailure if status.success() => return Ok(None),
-        RestartPolicy::OnSuccess if !status.success() => return Ok(None),
+        RestartPolicy::Never => return Restart::Never,
+        RestartPolicy::OnFailure if status.success() => return Restart::Never,
+        RestartPolicy::OnSuccess if !status.success() => return Restart::Never,
         _ => {}
     }
 
     if let Some(attempts) = cfg.restart_attempts {
         if attempts == 0 {
-            return Ok(None);
+            return Restart::Never;
         } else {
             cfg.restart_attempts = Some(attempts - 1);
         }
     }
 
-    if let Some(delay_sec) = cfg.restart_delay {
-        if delay {
-            sleep(Duration::from_secs(delay_sec))
+    let now = Instant::now();
+
+    if let Some(burst) = cfg.start_limit_burst {
+        let interval = Duration::from_secs(
+            cfg.start_limit_interval
+                .unwrap_or(DEFAULT_START_LIMIT_INTERVAL),
+        );
+
+        let recent = restarts
+            .starts
+            .iter()
+            .filter(|s| now.duration_since(**s) < interval)
+            .count();
+
+        if recent as u64 >= burst {
+            return Restart::Failed;
         }
     }
 
-    spawn(cfg).map(Some)
+    let delay = Duration::from_secs(cfg.restart_delay.unwrap_or(0));
+
+    let max_delay = if let Some(max_delay) = cfg.restart_max_delay {
+        Duration::from_secs(max_delay)
+    } else {
+        return Restart::After(delay);
+    };
+
+    // doubling a zero delay would never back off
+    let delay = if delay.is_zero() {
+        Duration::from_secs(DEFAULT_BACKOFF_DELAY)
+    } else {
+        delay
+    };
+
+    // a process which stayed up for longer than the maximum delay is considered healthy again
+    if restarts
+        .starts
+        .back()
+        .is_some_and(|s| now.duration_since(*s) >= max_delay)
+    {
+        restarts.failures = 0;
+    }
+
+    let backoff = delay
+        .saturating_mul(2u32.saturating_pow(restarts.failures))
+        .min(max_delay);
+
+    restarts.failures = restarts.failures.saturating_add(1);
+
+    // up to a quarter off so processes failing together don't restart in lockstep
+    Restart::After(backoff.mul_f64(1.0 - fastrand::f64() * 0.25))
+}
+
+/// Reads the PID of a forked service from `path`.
+///
+/// Daemons may exit before writing their PID file, so the file is polled for a short while.
+pub fn read_pid_file(path: &str) -> Result<Pid> {
+    let mut attempts = 10;
+
+    loop {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let pid = contents
+                    .trim()
+                    .parse::<i32>()
+                    .context("failed to parse PID file")?;
+
+                return Ok(Pid::from_raw(pid));
+            }
+            Err(e) if attempts == 0 => {
+                let path = path.to_string();
+
+                return Err(e).with_context(move || format!("failed to read PID file `{}`", path));
+            }
+            Err(_) => {
+                attempts -= 1;
+                sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+/// Restores sane terminal settings and clears the screen, leftovers from a previous session may
+/// have broken them.
+pub fn reset_tty(mut tty: &File) -> Result<()> {
+    let mut termios = tcgetattr(tty).context("failed to get tty attributes")?;
+
+    termios.input_flags &= !(InputFlags::IGNBRK
+        | InputFlags::BRKINT
+        | InputFlags::ISTRIP
+        | InputFlags::INLCR
+        | InputFlags::IGNCR);
+    termios.input_flags |= InputFlags::ICRNL | InputFlags::IMAXBEL | InputFlags::IUTF8;
+    termios.output_flags |= OutputFlags::OPOST | OutputFlags::ONLCR;
+    termios.control_flags |= ControlFlags::CREAD;
+    termios.local_flags = LocalFlags::ISIG
+        | LocalFlags::ICANON
+        | LocalFlags::IEXTEN
+        | LocalFlags::ECHO
+        | LocalFlags::ECHOE
+        | LocalFlags::ECHOK
+        | LocalFlags::ECHOCTL
+        | LocalFlags::ECHOKE;
+
+    for (i, c) in [
+        (SpecialCharacterIndices::VINTR, 0o3),
+        (SpecialCharacterIndices::VQUIT, 0o34),
+        (SpecialCharacterIndices::VERASE, 0o177),
+        (SpecialCharacterIndices::VKILL, 0o25),
+        (SpecialCharacterIndices::VEOF, 0o4),
+        (SpecialCharacterIndices::VSTART, 0o21),
+        (SpecialCharacterIndices::VSTOP, 0o23),
+        (SpecialCharacterIndices::VSUSP, 0o32),
+        (SpecialCharacterIndices::VTIME, 0),
+        (SpecialCharacterIndices::VMIN, 1),
+    ] {
+        termios.control_chars[i as usize] = c;
+    }
+
+    tcsetattr(tty, SetArg::TCSANOW, &termios).context("failed to set tty attributes")?;
+    tcflush(tty, FlushArg::TCIOFLUSH).context("failed to flush tty")?;
+
+    // reset the terminal and clear the screen and scrollback
+    tty.write_all(b"\x1bc\x1b[r\x1b[H\x1b[3J")
+        .context("failed to reset tty")?;
+
+    Ok(())
+}
+
+extern "C" {
+    static mut environ: *const *const libc::c_char;
+}
+
+/// The environment of a process listening on sockets, built before forking with room for
+/// `LISTEN_PID` which is only known once forked, where nothing can be allocated.
+struct ListenEnv {
+    // `KEY=value` entries ending in a nul, `LISTEN_PID` being the last
+    vars: Vec<Vec<u8>>,
+    ptrs: Vec<*const libc::c_char>,
+}
+
+// SAFETY: the pointers only refer to the entries owned alongside them
+unsafe impl Send for ListenEnv {}
+unsafe impl Sync for ListenEnv {}
+
+impl ListenEnv {
+    fn new(vars: &HashMap<String, String>, fds: usize) -> Self {
+        let mut vars = vars
+            .iter()
+            .filter(|(k, _)| !k.starts_with("LISTEN_"))
+            .map(|(k, v)| format!("{}={}\0", k, v).into_bytes())
+            .chain([format!("LISTEN_FDS={}\0", fds).into_bytes()])
+            .collect::<Vec<_>>();
+
+        // room for any pid and the nul
+        vars.push([b"LISTEN_PID=".as_slice(), &[0; 11]].concat());
+
+        let ptrs = vars
+            .iter()
+            .map(|v| v.as_ptr().cast())
+            .chain([ptr::null()])
+            .collect();
+
+        Self { vars, ptrs }
+    }
+
+    /// Fills in the pid of the calling process and replaces its environment, which the process
+    /// is executed with as the environment of the command wasn't modified.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called in the child after forking.
+    unsafe fn apply(&mut self) -> std::io::Result<()> {
+        // unwrap: `LISTEN_PID` is always the last entry
+        let mut digits = &mut self.vars.last_mut().unwrap()[11..];
+        write!(digits, "{}", process::id())?;
+
+        environ = self.ptrs.as_ptr();
+
+        Ok(())
+    }
 }
 
 pub fn spawn(cfg: &Supervisor) -> Result<Child> {
-    let mut cmd = Command::new(&cfg.cmd);
+    let (pairs, vars) = environment(cfg)?;
 
-    cmd.args(&cfg.args);
-    cmd.envs(cfg.env.iter().filter_map(|pair| pair.split_once('=')));
+    let mut cmd = Command::new(expand(&cfg.cmd, &vars));
+
+    cmd.args(cfg.args.iter().map(|arg| expand(arg, &vars)));
+
+    // the supervisor blocks the signals it handles, which the process would otherwise inherit
+    // SAFETY: we only call async-signal-safe functions (sigprocmask)
+    unsafe {
+        cmd.pre_exec(|| {
+            SigSet::all().thread_unblock()?;
+            Ok(())
+        });
+    }
+
+    if let Some(fds) = cfg.listen_fds {
+        let mut listen_env = ListenEnv::new(&vars, fds);
+
+        // SAFETY: we only call async-signal-safe functions (getpid), nothing is allocated
+        unsafe {
+            cmd.pre_exec(move || listen_env.apply());
+        }
+    } else {
+        cmd.envs(pairs);
+    }
 
     if let Some(ref dir) = cfg.pwd {
-        cmd.current_dir(dir);
+        cmd.current_dir(expand(dir, &vars));
     }
 
     if let Some(ref dir) = cfg.root {
@@ -87,14 +337,72 @@ pub fn spawn(cfg: &Supervisor) -> Result<Child> {
         cmd.gid(gid);
     }
 
+    let tty = if cfg.stdin == Some(StdinMode::Tty) {
+        let path = expand(
+            cfg.tty_path
+                .as_ref()
+                .context("stdin `tty` requires a tty path")?,
+            &vars,
+        );
+
+        // the tty becomes the controlling terminal of the process, not the supervisor
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(OFlag::O_NOCTTY.bits())
+            .open(path)
+            .context("failed to open tty")?;
+
+        if cfg.tty_reset {
+            reset_tty(&tty)?;
+        }
+
+        Some(tty)
+    } else {
+        None
+    };
+
+    // a tty can only be acquired by a session leader
+    if cfg.setsid || tty.is_some() {
+        let ctty = tty.is_some();
+
+        // SAFETY: we only call async-signal-safe functions (setsid, ioctl)
+        unsafe {
+            cmd.pre_exec(move || {
+                setsid()?;
+
+                // stdin has already been replaced by the tty
+                if ctty && libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+    }
+
+    match cfg.stdin {
+        Some(StdinMode::Null) => {
+            cmd.stdin(Stdio::null());
+        }
+        Some(StdinMode::Tty) => {
+            if let Some(ref tty) = tty {
+                cmd.stdin(tty.try_clone().context("failed to duplicate tty")?);
+            }
+        }
+        None => {}
+    }
+
     if let Some(ref stdout) = cfg.stdout {
         let f = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(stdout)
+            .open(expand(stdout, &vars))
             .context("failed to open stdout")?;
 
         cmd.stdout(f);
+    } else if let Some(ref tty) = tty {
+        cmd.stdout(tty.try_clone().context("failed to duplicate tty")?);
     } else {
         cmd.stdout(Stdio::null());
     }
@@ -103,13 +411,97 @@ pub fn spawn(cfg: &Supervisor) -> Result<Child> {
         let f = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(stderr)
+            .open(expand(stderr, &vars))
             .context("failed to open stderr")?;
 
         cmd.stderr(f);
+    } else if let Some(tty) = tty {
+        cmd.stderr(tty);
     } else {
         cmd.stderr(Stdio::null());
     }
 
     cmd.spawn().context("failed to spawn child")
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+
+    use super::*;
+
+    fn failed() -> ExitStatus {
+        ExitStatus::from_raw(1 << 8)
+    }
+
+    fn always() -> Supervisor {
+        Supervisor {
+            restart_policy: Some(RestartPolicy::Always),
+            ..Default::default()
+        }
+    }
+
+    fn delay(restart: Restart) -> Duration {
+        match restart {
+            Restart::After(delay) => delay,
+            Restart::Never => panic!("expected a restart, got never"),
+            Restart::Failed => panic!("expected a restart, got failed"),
+        }
+    }
+
+    #[test]
+    fn burst_limit() {
+        let mut cfg = always();
+        cfg.start_limit_burst = Some(3);
+
+        let mut restarts = Restarts::new(&cfg);
+
+        for _ in 0..2 {
+            restarts.started();
+            delay(next_restart(&mut cfg, &mut restarts, failed()));
+        }
+
+        restarts.started();
+
+        assert!(matches!(
+            next_restart(&mut cfg, &mut restarts, failed()),
+            Restart::Failed
+        ));
+
+        restarts.reset();
+        restarts.started();
+
+        delay(next_restart(&mut cfg, &mut restarts, failed()));
+    }
+
+    #[test]
+    fn backoff_cap() {
+        let mut cfg = always();
+        cfg.restart_delay = Some(1);
+        cfg.restart_max_delay = Some(4);
+
+        let mut restarts = Restarts::new(&cfg);
+
+        for expected in [1, 2, 4, 4, 4] {
+            restarts.started();
+
+            let expected = Duration::from_secs(expected);
+            let delay = delay(next_restart(&mut cfg, &mut restarts, failed()));
+
+            // jitter takes up to a quarter off
+            assert!(delay <= expected && delay >= expected.mul_f64(0.75));
+        }
+    }
+
+    #[test]
+    fn backoff_without_delay() {
+        let mut cfg = always();
+        cfg.restart_max_delay = Some(8);
+
+        let mut restarts = Restarts::new(&cfg);
+
+        restarts.started();
+
+        assert!(!delay(next_restart(&mut cfg, &mut restarts, failed())).is_zero());
+    }
+}
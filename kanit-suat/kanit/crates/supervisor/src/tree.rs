@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fs;
+
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+
+// the parent is the second field after the command, which may contain spaces and parentheses
+fn parent_of(stat: &str) -> Option<i32> {
+    stat[stat.rfind(')')? + 1..]
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// Every descendant of `root`, found by walking `/proc`.
+pub fn descendants(root: Pid) -> Vec<Pid> {
+    let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+
+    for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let pid = match entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<i32>().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        // the process may have exited in the meantime
+        if let Some(parent) = fs::read_to_string(entry.path().join("stat"))
+            .ok()
+            .and_then(|s| parent_of(&s))
+        {
+            children.entry(parent).or_default().push(pid);
+        }
+    }
+
+    let mut found = vec![];
+    let mut stack = vec![root.as_raw()];
+
+    while let Some(pid) = stack.pop() {
+        if let Some(c) = children.get(&pid) {
+            found.extend(c.iter().map(|p| Pid::from_raw(*p)));
+            stack.extend(c);
+        }
+    }
+
+    found
+}
+
+/// Signals every descendant of `root`.
+pub fn kill_tree(root: Pid, signal: Signal) {
+    for pid in descendants(root) {
+        // ignore processes which already exited
+        let _ = kill(pid, signal);
+    }
+}
@@ -1,8 +1,10 @@
 // A needs B | A -> B
 // A uses B | <ignored>
 // A wants B | A -> B (if impossible tree, it will be discarded)
-// A before B | B -> A
-// A after B | A -> B
+// A before B | B -> A (if impossible tree, it will be discarded first)
+// A after B | A -> B (if impossible tree, it will be discarded first)
+// A conflicts B | <ignored>, A and B can't be started together
+// A provides B | dependencies on B resolve to A
 
 use std::rc::Rc;
 
@@ -20,6 +22,8 @@ use crate::{RcUnit, UnitName};
 #[derive(Debug, Clone)]
 pub struct UnitInfo {
     pub name: UnitName,
+    /// The file the unit was loaded from, used to report where dependencies come from.
+    pub source: Option<String>,
     pub dependencies: Rc<Dependencies>,
 }
 
@@ -27,6 +31,7 @@ impl UnitInfo {
     pub fn new(unit: &RcUnit) -> Self {
         Self {
             name: unit.borrow().name(),
+            source: unit.borrow().source().map(String::from),
             dependencies: Rc::new(unit.borrow().dependencies()),
         }
     }
@@ -50,6 +55,11 @@ pub struct Dependencies {
     pub before: Vec<UnitName>,
     /// The unit should run after another unit.
     pub after: Vec<UnitName>,
+    /// The unit can't run alongside another unit.
+    /// Starting the unit will stop the conflicting unit.
+    pub conflicts: Vec<UnitName>,
+    /// Virtual names other units can depend on instead of the unit itself, such as `logger`.
+    pub provides: Vec<UnitName>,
 }
 
 impl Dependencies {
@@ -81,4 +91,14 @@ impl Dependencies {
         self.after.push(dependency);
         self
     }
+
+    pub fn conflict(&mut self, dependency: UnitName) -> &mut Self {
+        self.conflicts.push(dependency);
+        self
+    }
+
+    pub fn provide(&mut self, name: UnitName) -> &mut Self {
+        self.provides.push(name);
+        self
+    }
 }
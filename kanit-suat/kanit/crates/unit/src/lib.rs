@@ -2,5 +2,6 @@ pub use dependencies::*;
 pub use unit::*;
 
 mod dependencies;
+pub mod socket;
 pub mod supervisor;
 mod unit;
@@ -0,0 +1,46 @@
+use std::fs;
+use std::net::{TcpListener, UdpSocket};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+
+use kanit_common::error::{Context, ErrorKind, Result, WithError};
+
+/// Binds a socket from a `kind:address` specification such as `tcp:127.0.0.1:8080` or
+/// `unix:/run/foo.sock`.
+///
+/// Supported kinds are `tcp`, `udp`, and `unix`.
+pub fn bind(spec: &str) -> Result<OwnedFd> {
+    let owned_spec = spec.to_string();
+
+    let (kind, addr) = spec
+        .split_once(':')
+        .with_context(move || format!("expected `kind:address` in `{}`", owned_spec))?;
+
+    let socket = match kind {
+        "tcp" => TcpListener::bind(addr).map(OwnedFd::from),
+        "udp" => UdpSocket::bind(addr).map(OwnedFd::from),
+        "unix" => {
+            // remove a stale socket left from a previous boot
+            if fs::metadata(addr).is_ok_and(|m| m.file_type().is_socket()) {
+                let _ = fs::remove_file(addr);
+            }
+
+            UnixListener::bind(addr).map(OwnedFd::from)
+        }
+        _ => {
+            let kind = kind.to_string();
+
+            Err(WithError::with(move || {
+                format!("unknown socket kind `{}`", kind)
+            }))?
+        }
+    };
+
+    let spec = spec.to_string();
+
+    socket.with_context_kind(
+        move || format!("failed to bind `{}`", spec),
+        ErrorKind::Recoverable,
+    )
+}
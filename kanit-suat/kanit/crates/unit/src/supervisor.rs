@@ -1,45 +1,120 @@
-use async_process::{Child, Command};
+use std::fs::File;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process;
+use std::time::Duration;
 
-use kanit_common::error::{Context, ErrorKind, Result};
-use kanit_supervisor::{RestartPolicy, Supervisor};
+use async_io::{Async, Timer};
+use futures_lite::{future, AsyncReadExt};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::{dup2, pipe2, Pid};
 
-pub struct SupervisorBuilder(Supervisor);
+use kanit_common::error::{Context, ErrorKind, Result, StaticError, WithError};
+use kanit_executor::process::{wait, Child, Command};
+use kanit_supervisor::{RestartPolicy, ServiceType, StdinMode, Supervisor, STOP_TIMEOUT};
+
+/// Seconds a supervisor is given to report that its process started.
+pub const START_TIMEOUT: u64 = 30;
+
+/// Stops a supervisor and waits for it to exit, killing it if it takes longer than it gives its
+/// process to stop. A pid of 0 means no supervisor was started.
+pub async fn stop(pid: u32) -> Result<()> {
+    // signalling 0 would reach every process in the group of the caller
+    if pid == 0 {
+        return Ok(());
+    }
+
+    kill(Pid::from_raw(pid as i32), Signal::SIGTERM)
+        .context_kind("failed to stop supervisor", ErrorKind::Recoverable)?;
+
+    let exited = async {
+        wait(pid).await;
+        true
+    };
+
+    let timeout = async {
+        Timer::after(Duration::from_secs(STOP_TIMEOUT + 5)).await;
+        false
+    };
+
+    if !future::or(exited, timeout).await {
+        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+
+        Err(StaticError("supervisor didn't stop in time")).kind(ErrorKind::Recoverable)?;
+    }
+
+    Ok(())
+}
+
+pub struct SupervisorBuilder(Supervisor, Vec<RawFd>);
 
 #[allow(dead_code)]
 impl SupervisorBuilder {
     pub fn new<S: ToString, I: IntoIterator<Item = S>>(cmd: S, args: I) -> Self {
-        Self(Supervisor {
-            cmd: cmd.to_string(),
-            args: args.into_iter().map(|s| s.to_string()).collect(),
-            restart_delay: None,
-            restart_attempts: None,
-            restart_policy: None,
-            pwd: None,
-            root: None,
-            env: vec![],
-            group: None,
-            user: None,
-            stdout: None,
-            stderr: None,
-        })
+        Self(
+            Supervisor {
+                cmd: cmd.to_string(),
+                args: args.into_iter().map(|s| s.to_string()).collect(),
+                name: None,
+                restart_delay: None,
+                restart_max_delay: None,
+                restart_attempts: None,
+                restart_policy: None,
+                start_limit_burst: None,
+                start_limit_interval: None,
+                service_type: None,
+                pid_file: None,
+                pwd: None,
+                root: None,
+                env: vec![],
+                environment_file: vec![],
+                group: None,
+                user: None,
+                stdout: None,
+                stderr: None,
+                stdin: None,
+                tty_path: None,
+                tty_reset: false,
+                setsid: false,
+                listen_fds: None,
+                lazy: false,
+                notify_fd: None,
+            },
+            vec![],
+        )
     }
 
     pub fn from_supervisor(supervisor: Supervisor) -> Self {
-        Self(supervisor)
+        Self(supervisor, vec![])
     }
 
     pub fn build(self) -> Supervisor {
         self.0
     }
 
-    pub fn spawn(self) -> Result<Child> {
+    /// Spawns the supervisor, waiting for it to report whether its process started.
+    pub async fn spawn(self) -> Result<Child> {
+        let (notify, notify_write) = pipe2(OFlag::O_CLOEXEC)
+            .context_kind("failed to create notify pipe", ErrorKind::Recoverable)?;
+
         let mut args = vec![];
 
+        if let Some(name) = self.0.name {
+            args.push("-n".to_string());
+            args.push(name);
+        }
+
         if let Some(delay) = self.0.restart_delay {
             args.push("-d".to_string());
             args.push(delay.to_string());
         }
 
+        if let Some(delay) = self.0.restart_max_delay {
+            args.push("--restart-max-delay".to_string());
+            args.push(delay.to_string());
+        }
+
         if let Some(attempts) = self.0.restart_attempts {
             args.push("-a".to_string());
             args.push(attempts.to_string());
@@ -50,6 +125,26 @@ impl SupervisorBuilder {
             args.push(policy.to_string());
         }
 
+        if let Some(burst) = self.0.start_limit_burst {
+            args.push("--start-limit-burst".to_string());
+            args.push(burst.to_string());
+        }
+
+        if let Some(interval) = self.0.start_limit_interval {
+            args.push("--start-limit-interval".to_string());
+            args.push(interval.to_string());
+        }
+
+        if let Some(service_type) = self.0.service_type {
+            args.push("-t".to_string());
+            args.push(service_type.to_string());
+        }
+
+        if let Some(pid_file) = self.0.pid_file {
+            args.push("--pid-file".to_string());
+            args.push(pid_file);
+        }
+
         if let Some(pwd) = self.0.pwd {
             args.push("-p".to_string());
             args.push(pwd);
@@ -65,6 +160,11 @@ impl SupervisorBuilder {
             args.push(pair);
         }
 
+        for file in self.0.environment_file {
+            args.push("--environment-file".to_string());
+            args.push(file);
+        }
+
         if let Some(group) = self.0.group {
             args.push("-g".to_string());
             args.push(group);
@@ -85,16 +185,125 @@ impl SupervisorBuilder {
             args.push(stderr);
         }
 
+        if let Some(stdin) = self.0.stdin {
+            args.push("--stdin".to_string());
+            args.push(stdin.to_string());
+        }
+
+        if let Some(tty_path) = self.0.tty_path {
+            args.push("--tty-path".to_string());
+            args.push(tty_path);
+        }
+
+        if self.0.tty_reset {
+            args.push("--tty-reset".to_string());
+        }
+
+        if self.0.setsid {
+            args.push("--setsid".to_string());
+        }
+
+        if let Some(fds) = self.0.listen_fds {
+            args.push("--listen-fds".to_string());
+            args.push(fds.to_string());
+        }
+
+        if self.0.lazy {
+            args.push("--lazy".to_string());
+        }
+
+        let mut fds = self.1;
+
+        // passed after the sockets
+        fds.push(notify_write.as_raw_fd());
+
+        args.push("--notify-fd".to_string());
+        args.push((2 + fds.len()).to_string());
+
         args.push("--".to_string());
 
         args.push(self.0.cmd);
 
         args.extend_from_slice(&self.0.args);
 
-        Command::new("kanit-supervisor")
-            .args(args)
+        let mut cmd = process::Command::new("kanit-supervisor");
+
+        cmd.args(args);
+
+        let mut moved = vec![0; fds.len()];
+
+        // SAFETY: we only call async-signal-safe functions (fcntl, dup2)
+        unsafe {
+            cmd.pre_exec(move || {
+                let base = 3 + fds.len() as RawFd;
+
+                // fds may already sit on the fds they are moved to, so move them out first
+                for (fd, moved) in fds.iter().zip(moved.iter_mut()) {
+                    *moved = fcntl(*fd, FcntlArg::F_DUPFD_CLOEXEC(base))?;
+                }
+
+                for (i, fd) in moved.iter().enumerate() {
+                    dup2(*fd, 3 + i as RawFd)?;
+                }
+
+                Ok(())
+            });
+        }
+
+        let child = Command::from(cmd)
             .spawn()
-            .context_kind("failed to spawn supervisor", ErrorKind::Recoverable)
+            .context_kind("failed to spawn supervisor", ErrorKind::Recoverable)?;
+
+        // only the supervisor holds the pipe open now
+        drop(notify_write);
+
+        let mut started = vec![];
+
+        let read = async {
+            Async::new(File::from(notify))?
+                .read_to_end(&mut started)
+                .await
+                .map(Some)
+        };
+
+        let timeout = async {
+            Timer::after(Duration::from_secs(START_TIMEOUT)).await;
+            Ok(None)
+        };
+
+        match future::or(read, timeout).await {
+            Ok(Some(_)) if started == b"ok" => Ok(child),
+            Ok(Some(_)) => {
+                let message = String::from_utf8_lossy(&started).into_owned();
+
+                // closed without a report, such as when the supervisor was killed
+                let message = if message.is_empty() {
+                    "supervisor exited before the process started".to_string()
+                } else {
+                    message
+                };
+
+                // the supervisor exits on its own once it has failed
+                let _ = stop(child.id()).await;
+
+                Err(WithError::with(move || message.clone())).kind(ErrorKind::Recoverable)
+            }
+            Ok(None) => {
+                stop(child.id()).await?;
+
+                Err(StaticError("process didn't start in time")).kind(ErrorKind::Recoverable)
+            }
+            Err(e) => {
+                stop(child.id()).await?;
+
+                Err(e).context_kind("failed to read notify pipe", ErrorKind::Recoverable)
+            }
+        }
+    }
+
+    pub fn name(mut self, name: String) -> Self {
+        self.0.name = Some(name);
+        self
     }
 
     pub fn restart_delay(mut self, delay: u64) -> Self {
@@ -102,6 +311,11 @@ impl SupervisorBuilder {
         self
     }
 
+    pub fn restart_max_delay(mut self, delay: u64) -> Self {
+        self.0.restart_max_delay = Some(delay);
+        self
+    }
+
     pub fn restart_attempts(mut self, attempts: u64) -> Self {
         self.0.restart_attempts = Some(attempts);
         self
@@ -112,6 +326,22 @@ impl SupervisorBuilder {
         self
     }
 
+    pub fn start_limit(mut self, burst: u64, interval: u64) -> Self {
+        self.0.start_limit_burst = Some(burst);
+        self.0.start_limit_interval = Some(interval);
+        self
+    }
+
+    pub fn service_type(mut self, service_type: ServiceType) -> Self {
+        self.0.service_type = Some(service_type);
+        self
+    }
+
+    pub fn pid_file(mut self, pid_file: String) -> Self {
+        self.0.pid_file = Some(pid_file);
+        self
+    }
+
     pub fn pwd(mut self, pwd: String) -> Self {
         self.0.pwd = Some(pwd);
         self
@@ -127,6 +357,11 @@ impl SupervisorBuilder {
         self
     }
 
+    pub fn environment_file(mut self, path: String) -> Self {
+        self.0.environment_file.push(path);
+        self
+    }
+
     pub fn group(mut self, group: String) -> Self {
         self.0.group = Some(group);
         self
@@ -146,4 +381,36 @@ impl SupervisorBuilder {
         self.0.stderr = Some(stderr);
         self
     }
+
+    pub fn stdin(mut self, stdin: StdinMode) -> Self {
+        self.0.stdin = Some(stdin);
+        self
+    }
+
+    pub fn tty_path(mut self, tty_path: String) -> Self {
+        self.0.tty_path = Some(tty_path);
+        self
+    }
+
+    pub fn tty_reset(mut self, tty_reset: bool) -> Self {
+        self.0.tty_reset = tty_reset;
+        self
+    }
+
+    pub fn setsid(mut self, setsid: bool) -> Self {
+        self.0.setsid = setsid;
+        self
+    }
+
+    /// Passes listening sockets to the process, starting from fd 3.
+    pub fn sockets(mut self, fds: Vec<RawFd>) -> Self {
+        self.0.listen_fds = (!fds.is_empty()).then_some(fds.len());
+        self.1 = fds;
+        self
+    }
+
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.0.lazy = lazy;
+        self
+    }
 }
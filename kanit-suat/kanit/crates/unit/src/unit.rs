@@ -13,12 +13,45 @@ use crate::Dependencies;
 pub type RcUnit = SendWrapper<Rc<RefCell<dyn Unit>>>;
 pub type UnitName = Arc<str>;
 
+/// Whether a unit should be started once its preconditions are checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preparation {
+    /// The unit can be started.
+    Ready,
+    /// The unit's conditions aren't met, so it is skipped rather than failed.
+    Skip(String),
+}
+
+/// Instance names such as `getty@tty1` are instantiated from a template such as `getty@`.
+pub trait UnitNameExt {
+    /// The template the unit is an instance of.
+    fn template(&self) -> Option<UnitName>;
+
+    /// The instance of the template, substituted for `%i`.
+    fn instance(&self) -> Option<&str>;
+}
+
+impl UnitNameExt for UnitName {
+    fn template(&self) -> Option<UnitName> {
+        self.instance()?;
+
+        self.split_once('@')
+            .map(|(t, _)| UnitName::from(format!("{}@", t)))
+    }
+
+    fn instance(&self) -> Option<&str> {
+        self.split_once('@')
+            .map(|(_, i)| i)
+            .filter(|i| !i.is_empty())
+    }
+}
+
 /// Unit lifecycle:
 ///
 ///
 /// Startup:
 /// ```rs
-/// if !unit.prepare().await? { return; }
+/// if let Preparation::Skip(_) = unit.prepare().await? { return; }
 ///
 /// unit.start().await?;
 /// ```
@@ -44,11 +77,27 @@ pub trait Unit: Send + Sync {
         None
     }
 
+    /// The file the unit was loaded from, if any.
+    fn source(&self) -> Option<&str> {
+        None
+    }
+
     /// Dependencies of the unit.
     fn dependencies(&self) -> Dependencies {
         Dependencies::new()
     }
 
+    /// The process started for the unit, usually its supervisor.
+    fn pid(&self) -> Option<u32> {
+        None
+    }
+
+    /// Binds the sockets the unit is activated through.
+    /// Called for every unit of a level before any unit of that level is started.
+    fn listen(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     /// Starts the unit.
     async fn start(&mut self) -> Result<()> {
         Ok(())
@@ -60,8 +109,8 @@ pub trait Unit: Send + Sync {
     }
 
     /// Preconditions for starting a unit.
-    async fn prepare(&self) -> Result<bool> {
-        Ok(true)
+    async fn prepare(&self) -> Result<Preparation> {
+        Ok(Preparation::Ready)
     }
 
     /// Tearing down a unit once finished.
@@ -4,3 +4,4 @@ mod loader;
 mod mounts;
 pub mod oneshot;
 pub mod services;
+pub mod targets;
@@ -4,8 +4,9 @@ use kanit_unit::{wrap_unit, RcUnit, UnitName};
 
 use crate::oneshot::*;
 use crate::services::*;
+use crate::targets::*;
 
-pub fn baked_units() -> [RcUnit; 16] {
+pub fn baked_units() -> [RcUnit; 17] {
     [
         wrap_unit(ProcFs),
         wrap_unit(SysFs),
@@ -18,6 +19,7 @@ pub fn baked_units() -> [RcUnit; 16] {
         wrap_unit(RootFs),
         wrap_unit(Swap),
         wrap_unit(LocalMount),
+        wrap_unit(LocalFs),
         wrap_unit(Seed),
         wrap_unit(Hostname),
         wrap_unit(Syslog::new()),
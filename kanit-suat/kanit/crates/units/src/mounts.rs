@@ -1,9 +1,8 @@
 use std::collections::HashMap;
 use std::path::Path;
 
-use async_process::Command;
-
 use kanit_common::error::{Context, Result};
+use kanit_executor::process::Command;
 
 pub async fn is_fs_available(fs: &str) -> Result<bool> {
     let filesystems = async_fs::read_to_string("/proc/filesystems")
@@ -46,6 +45,7 @@ pub async fn try_mount_from_fstab_action<P: AsRef<Path>>(
     }
 }
 
+#[allow(dead_code)]
 pub struct MountEntry<'a> {
     pub fs_spec: &'a str,
     pub fs_file: &'a str,
@@ -144,7 +144,7 @@ impl<'a> MountEntry<'a> {
     }
 }
 
-pub fn parse_mounts(lines: &str) -> Result<Vec<MountEntry>> {
+pub fn parse_mounts(lines: &str) -> Result<Vec<MountEntry<'_>>> {
     lines
         .lines()
         .filter_map(|line| {
@@ -1,11 +1,11 @@
 use std::path::Path;
 
-use async_process::{Command, Stdio};
 use async_trait::async_trait;
 use futures_lite::StreamExt;
 use log::{info, warn};
 
 use kanit_common::error::{Context, ErrorKind, Result, StaticError};
+use kanit_executor::process::{Command, Stdio};
 use kanit_unit::{Dependencies, Unit};
 
 use crate::oneshot::Modules;
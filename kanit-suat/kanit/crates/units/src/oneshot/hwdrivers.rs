@@ -1,6 +1,5 @@
 use std::collections::HashSet;
 
-use async_process::{Command, Stdio};
 use async_trait::async_trait;
 use blocking::unblock;
 use futures_lite::stream::iter;
@@ -9,6 +8,7 @@ use log::info;
 use walkdir::WalkDir;
 
 use kanit_common::error::{Context, Result};
+use kanit_executor::process::{Command, Stdio};
 use kanit_unit::{Dependencies, Unit};
 
 use crate::oneshot::{DevFs, SysFs};
@@ -1,9 +1,9 @@
-use async_process::{Command, Stdio};
 use async_trait::async_trait;
 use log::info;
 use nix::unistd::sync;
 
 use kanit_common::error::{Context, ErrorKind, Result, StaticError};
+use kanit_executor::process::{Command, Stdio};
 use kanit_unit::{Dependencies, Unit};
 
 use crate::mounts::{is_fs_mounted, parse_mounts};
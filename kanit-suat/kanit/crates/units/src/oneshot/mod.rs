@@ -10,6 +10,7 @@ pub use rootfs::RootFs;
 pub use run::Run;
 pub use seed::Seed;
 pub use swap::Swap;
+pub use sysctl::Sysctl;
 pub use sysfs::SysFs;
 
 mod clock;
@@ -1,6 +1,5 @@
 use std::os::unix::ffi::OsStrExt;
 
-use async_process::Command;
 use async_trait::async_trait;
 use blocking::unblock;
 use futures_lite::stream::iter;
@@ -9,6 +8,7 @@ use log::{info, warn};
 use walkdir::WalkDir;
 
 use kanit_common::error::{Context, Result};
+use kanit_executor::process::Command;
 use kanit_unit::Unit;
 
 use crate::unit_name;
@@ -19,7 +19,8 @@ use kanit_common::constants;
 use kanit_common::error::{Context, ErrorKind, Result};
 use kanit_unit::{Dependencies, Unit};
 
-use crate::oneshot::{Clock, LocalMount};
+use crate::oneshot::Clock;
+use crate::targets::LocalFs;
 use crate::unit_name;
 
 pub struct Seed;
@@ -111,7 +112,7 @@ impl Unit for Seed {
 
     fn dependencies(&self) -> Dependencies {
         Dependencies::new()
-            .need(LocalMount.name())
+            .need(LocalFs.name())
             .after(Clock.name())
             .clone()
     }
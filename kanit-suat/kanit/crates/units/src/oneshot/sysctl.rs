@@ -1,8 +1,8 @@
-use async_process::{Command, Stdio};
 use async_trait::async_trait;
 use log::info;
 
 use kanit_common::error::{Context, ErrorKind, Result, StaticError};
+use kanit_executor::process::{Command, Stdio};
 use kanit_unit::{Dependencies, Unit};
 
 use crate::oneshot::Clock;
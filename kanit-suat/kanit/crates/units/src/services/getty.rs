@@ -37,15 +37,21 @@ impl Unit for GeTTY {
             .clone()
     }
 
+    fn pid(&self) -> Option<u32> {
+        (self.pid != 0).then_some(self.pid)
+    }
+
     async fn start(&mut self) -> Result<()> {
         let child = if self.serial {
             SupervisorBuilder::new("getty", ["-L", "0", self.tty, "vt100"])
         } else {
             SupervisorBuilder::new("getty", ["38400", self.tty])
         }
+        .name(self.name.clone())
         .restart_policy(RestartPolicy::Always)
         .restart_delay(2)
-        .spawn()?;
+        .spawn()
+        .await?;
 
         self.pid = child.id();
 
@@ -53,9 +59,15 @@ impl Unit for GeTTY {
     }
 
     async fn stop(&mut self) -> Result<()> {
+        if self.pid == 0 {
+            return Ok(());
+        }
+
         let pid = self.pid;
         let _ = unblock(move || kill(Pid::from_raw(pid as i32), Signal::SIGKILL)).await;
 
+        self.pid = 0;
+
         Ok(())
     }
 }
@@ -7,9 +7,10 @@ use nix::unistd::Pid;
 use kanit_common::error::Result;
 use kanit_supervisor::RestartPolicy;
 use kanit_unit::supervisor::SupervisorBuilder;
-use kanit_unit::{Dependencies, Unit};
+use kanit_unit::{Dependencies, Unit, UnitName};
 
-use crate::oneshot::{Clock, Hostname, LocalMount};
+use crate::oneshot::{Clock, Hostname};
+use crate::targets::LocalFs;
 use crate::unit_name;
 
 pub struct Syslog {
@@ -31,16 +32,23 @@ impl Unit for Syslog {
         Dependencies::new()
             .need(Clock.name())
             .need(Hostname.name())
-            .need(LocalMount.name())
+            .need(LocalFs.name())
+            .provide(UnitName::from("logger"))
             .clone()
     }
 
+    fn pid(&self) -> Option<u32> {
+        (self.pid != 0).then_some(self.pid)
+    }
+
     async fn start(&mut self) -> Result<()> {
         info!("starting syslog");
 
         let child = SupervisorBuilder::new("syslogd", [])
+            .name(self.name().to_string())
             .restart_policy(RestartPolicy::OnFailure)
-            .spawn()?;
+            .spawn()
+            .await?;
 
         self.pid = child.id();
 
@@ -48,9 +56,15 @@ impl Unit for Syslog {
     }
 
     async fn stop(&mut self) -> Result<()> {
+        if self.pid == 0 {
+            return Ok(());
+        }
+
         let pid = self.pid;
         let _ = unblock(move || kill(Pid::from_raw(pid as i32), Signal::SIGKILL)).await;
 
+        self.pid = 0;
+
         Ok(())
     }
 }
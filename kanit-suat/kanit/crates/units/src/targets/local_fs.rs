@@ -0,0 +1,27 @@
+use async_trait::async_trait;
+
+use kanit_unit::{Dependencies, Unit};
+
+use crate::oneshot::{LocalMount, RootFs, Swap};
+use crate::unit_name;
+
+/// Reached once local filesystems are mounted.
+pub struct LocalFs;
+
+#[async_trait]
+impl Unit for LocalFs {
+    unit_name!("local-fs");
+
+    fn description(&self) -> Option<&str> {
+        Some("local filesystems")
+    }
+
+    // swap isn't needed to use the filesystems
+    fn dependencies(&self) -> Dependencies {
+        Dependencies::new()
+            .need(RootFs.name())
+            .need(LocalMount.name())
+            .want(Swap.name())
+            .clone()
+    }
+}
@@ -0,0 +1,3 @@
+pub use local_fs::LocalFs;
+
+mod local_fs;